@@ -0,0 +1,36 @@
+use axum_raw_websocket::codec::mask::{apply_mask, apply_mask_scalar};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+const KEY: [u8; 4] = [0x6d, 0xb6, 0xb2, 0x80];
+const SIZES: [usize; 7] = [7, 64, 512, 4 << 10, 64 << 10, 1 << 20, 16 << 20];
+
+fn apply_mask_bytewise(buf: &mut [u8], key: [u8; 4]) {
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte ^= key[i & 3];
+    }
+}
+
+fn unmask(c: &mut Criterion) {
+    let mut group = c.benchmark_group("unmask");
+
+    for size in SIZES {
+        let mut buf = vec![0xa5; size];
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("bytewise", size), &size, |b, _| {
+            b.iter(|| apply_mask_bytewise(black_box(&mut buf), black_box(KEY)))
+        });
+        group.bench_with_input(BenchmarkId::new("scalar", size), &size, |b, _| {
+            b.iter(|| apply_mask_scalar(black_box(&mut buf), black_box(KEY)))
+        });
+        group.bench_with_input(BenchmarkId::new("simd", size), &size, |b, _| {
+            b.iter(|| apply_mask(black_box(&mut buf), black_box(KEY)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, unmask);
+criterion_main!(benches);
@@ -0,0 +1,885 @@
+//! A minimal [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455#section-5) frame codec for
+//! [`tokio_util::codec`].
+//!
+//! [`WsFrameCodec`] decodes and encodes individual WebSocket frames on the server side of a
+//! connection. It validates everything that can be checked on a single frame, such as masking,
+//! control frame constraints, the order of continuation frames, the frame size limit and close
+//! codes, but leaves reassembling messages to the caller.
+//!
+//! [`WsMessageCodec`] builds on top of it and yields complete messages, joining fragments and
+//! validating text messages.
+//!
+//! Use [`RawSocketUpgrade::on_upgrade_framed`](crate::RawSocketUpgrade::on_upgrade_framed) or
+//! [`RawSocketUpgrade::on_upgrade_messages`](crate::RawSocketUpgrade::on_upgrade_messages) to get
+//! a [`Framed`] stream for an upgraded connection.
+
+use crate::{OnFailedUpgrade, RawSocketUpgrade};
+use axum::response::Response;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+pub mod mask;
+mod message;
+
+pub use message::{DEFAULT_MAX_MESSAGE_SIZE, Message, MessageError, WsMessageCodec};
+
+/// The default limit for the payload of a single frame, 16 MiB.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 << 20;
+
+/// The largest payload a control frame may carry.
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+const FIN: u8 = 0x80;
+const RSV1: u8 = 0x40;
+const RSV2: u8 = 0x20;
+const RSV3: u8 = 0x10;
+const OPCODE: u8 = 0x0f;
+const MASK: u8 = 0x80;
+const PAYLOAD_LEN: u8 = 0x7f;
+
+/// The opcode of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpCode {
+    /// Continues a fragmented message.
+    Continuation,
+    /// Starts a text message.
+    Text,
+    /// Starts a binary message.
+    Binary,
+    /// Closes the connection.
+    Close,
+    /// Asks the peer for a pong.
+    Ping,
+    /// Answers a ping.
+    Pong,
+}
+
+impl OpCode {
+    /// Whether this is a control frame opcode.
+    pub fn is_control(self) -> bool {
+        matches!(self, Self::Close | Self::Ping | Self::Pong)
+    }
+
+    fn from_u8(byte: u8) -> Option<Self> {
+        match byte {
+            0x0 => Some(Self::Continuation),
+            0x1 => Some(Self::Text),
+            0x2 => Some(Self::Binary),
+            0x8 => Some(Self::Close),
+            0x9 => Some(Self::Ping),
+            0xa => Some(Self::Pong),
+            _ => None,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Self::Continuation => 0x0,
+            Self::Text => 0x1,
+            Self::Binary => 0x2,
+            Self::Close => 0x8,
+            Self::Ping => 0x9,
+            Self::Pong => 0xa,
+        }
+    }
+}
+
+/// A status code sent in a close frame.
+///
+/// See [RFC 6455 section 7.4](https://datatracker.ietf.org/doc/html/rfc6455#section-7.4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CloseCode(u16);
+
+impl CloseCode {
+    /// `1000`, the purpose of the connection has been fulfilled.
+    pub const NORMAL: Self = Self(1000);
+    /// `1001`, the endpoint is going away.
+    pub const GOING_AWAY: Self = Self(1001);
+    /// `1002`, the peer violated the protocol.
+    pub const PROTOCOL_ERROR: Self = Self(1002);
+    /// `1003`, the peer sent a type of data that can not be accepted.
+    pub const UNSUPPORTED_DATA: Self = Self(1003);
+    /// `1007`, a message contained data inconsistent with its type, e.g. invalid UTF-8.
+    pub const INVALID_PAYLOAD: Self = Self(1007);
+    /// `1008`, a message violated a policy of the endpoint.
+    pub const POLICY_VIOLATION: Self = Self(1008);
+    /// `1009`, a message was too big to process.
+    pub const MESSAGE_TOO_BIG: Self = Self(1009);
+    /// `1010`, the server did not negotiate an extension the client requires.
+    pub const MANDATORY_EXTENSION: Self = Self(1010);
+    /// `1011`, the server encountered an unexpected condition.
+    pub const INTERNAL_ERROR: Self = Self(1011);
+
+    /// Create a close code from its numeric value.
+    pub const fn new(code: u16) -> Self {
+        Self(code)
+    }
+
+    /// The numeric value of the close code.
+    pub const fn as_u16(self) -> u16 {
+        self.0
+    }
+
+    /// Whether the code may be sent in a close frame.
+    ///
+    /// This excludes codes below 1000, the codes reserved for internal use (1004, 1005, 1006
+    /// and 1015), unassigned codes up to 2999 and codes of 5000 and above.
+    pub fn is_sendable(self) -> bool {
+        matches!(self.0, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        Self(code)
+    }
+}
+
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> Self {
+        code.0
+    }
+}
+
+impl fmt::Display for CloseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A single WebSocket frame with an unmasked payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    fin: bool,
+    rsv1: bool,
+    opcode: OpCode,
+    payload: Bytes,
+}
+
+impl Frame {
+    /// Create a frame.
+    pub fn new(fin: bool, opcode: OpCode, payload: impl Into<Bytes>) -> Self {
+        Self {
+            fin,
+            rsv1: false,
+            opcode,
+            payload: payload.into(),
+        }
+    }
+
+    /// A final text frame. The payload must be valid UTF-8.
+    pub fn text(payload: impl Into<Bytes>) -> Self {
+        Self::new(true, OpCode::Text, payload)
+    }
+
+    /// A final binary frame.
+    pub fn binary(payload: impl Into<Bytes>) -> Self {
+        Self::new(true, OpCode::Binary, payload)
+    }
+
+    /// A ping frame.
+    pub fn ping(payload: impl Into<Bytes>) -> Self {
+        Self::new(true, OpCode::Ping, payload)
+    }
+
+    /// A pong frame.
+    pub fn pong(payload: impl Into<Bytes>) -> Self {
+        Self::new(true, OpCode::Pong, payload)
+    }
+
+    /// A close frame carrying a status code and a reason.
+    pub fn close(code: CloseCode, reason: &str) -> Self {
+        let mut payload = BytesMut::with_capacity(2 + reason.len());
+        payload.put_u16(code.as_u16());
+        payload.put_slice(reason.as_bytes());
+        Self::new(true, OpCode::Close, payload.freeze())
+    }
+
+    /// A close frame without a status code.
+    pub fn close_empty() -> Self {
+        Self::new(true, OpCode::Close, Bytes::new())
+    }
+
+    /// Set the RSV1 bit, which marks a compressed message if `permessage-deflate` was negotiated.
+    pub fn with_rsv1(mut self, rsv1: bool) -> Self {
+        self.rsv1 = rsv1;
+        self
+    }
+
+    /// Whether this is the final frame of a message.
+    pub fn is_final(&self) -> bool {
+        self.fin
+    }
+
+    /// Whether the RSV1 bit is set.
+    pub fn rsv1(&self) -> bool {
+        self.rsv1
+    }
+
+    /// The opcode of the frame.
+    pub fn opcode(&self) -> OpCode {
+        self.opcode
+    }
+
+    /// The unmasked payload.
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    /// Consume the frame and return its payload.
+    pub fn into_payload(self) -> Bytes {
+        self.payload
+    }
+
+    /// The status code and reason of a close frame.
+    ///
+    /// Returns `None` if this is not a close frame or it does not carry a status code. The
+    /// decoder guarantees that the reason of received close frames is valid UTF-8.
+    pub fn close_reason(&self) -> Option<(CloseCode, &str)> {
+        if self.opcode != OpCode::Close || self.payload.len() < 2 {
+            return None;
+        }
+
+        let code = CloseCode(u16::from_be_bytes([self.payload[0], self.payload[1]]));
+        let reason = std::str::from_utf8(&self.payload[2..]).ok()?;
+        Some((code, reason))
+    }
+}
+
+/// An error while decoding or encoding frames.
+///
+/// Every variant but [`FrameError::Io`] is a protocol violation after which the connection
+/// should be closed with [`FrameError::close_code`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FrameError {
+    /// Reading from or writing to the socket failed.
+    Io(io::Error),
+    /// A reserved bit was set without an extension defining it.
+    ReservedBits,
+    /// The frame used an unknown opcode.
+    InvalidOpCode(u8),
+    /// A frame from the client was not masked.
+    UnmaskedFrame,
+    /// A control frame was fragmented.
+    FragmentedControlFrame,
+    /// A control frame carried more than 125 bytes.
+    ControlFrameTooLarge,
+    /// The payload length was not encoded in the minimal number of bytes or had the most
+    /// significant bit set.
+    InvalidPayloadLength,
+    /// The frame payload exceeded the configured limit.
+    FrameTooLarge {
+        /// The size of the payload.
+        size: u64,
+        /// The configured limit.
+        max: usize,
+    },
+    /// A continuation frame was received without a preceding fragmented message.
+    UnexpectedContinuation,
+    /// A new data frame was received while a fragmented message was still in progress.
+    ExpectedContinuation,
+    /// A close frame carried a one byte payload.
+    InvalidClosePayload,
+    /// A close frame carried a status code that must not be sent.
+    InvalidCloseCode(u16),
+    /// The reason of a close frame was not valid UTF-8.
+    InvalidCloseReason,
+}
+
+impl FrameError {
+    /// The close code to send to the peer in response to this error.
+    pub fn close_code(&self) -> CloseCode {
+        match self {
+            Self::Io(_) => CloseCode::INTERNAL_ERROR,
+            Self::FrameTooLarge { .. } => CloseCode::MESSAGE_TOO_BIG,
+            Self::InvalidCloseReason => CloseCode::INVALID_PAYLOAD,
+            _ => CloseCode::PROTOCOL_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::ReservedBits => f.write_str("reserved bits set without a negotiated extension"),
+            Self::InvalidOpCode(opcode) => write!(f, "invalid opcode {opcode:#x}"),
+            Self::UnmaskedFrame => f.write_str("frame from client is not masked"),
+            Self::FragmentedControlFrame => f.write_str("control frame is fragmented"),
+            Self::ControlFrameTooLarge => f.write_str("control frame payload exceeds 125 bytes"),
+            Self::InvalidPayloadLength => f.write_str("payload length is not minimally encoded"),
+            Self::FrameTooLarge { size, max } => {
+                write!(
+                    f,
+                    "frame payload of {size} bytes exceeds limit of {max} bytes"
+                )
+            }
+            Self::UnexpectedContinuation => {
+                f.write_str("continuation frame without a fragmented message")
+            }
+            Self::ExpectedContinuation => {
+                f.write_str("new data frame while a fragmented message is in progress")
+            }
+            Self::InvalidClosePayload => f.write_str("close frame payload of one byte"),
+            Self::InvalidCloseCode(code) => write!(f, "invalid close code {code}"),
+            Self::InvalidCloseReason => f.write_str("close reason is not valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FrameError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// A server side [`Decoder`] and [`Encoder`] for WebSocket frames.
+///
+/// Decoded frames must be masked by the client and are returned unmasked, encoded frames are sent
+/// unmasked as required for servers.
+#[derive(Debug, Clone)]
+pub struct WsFrameCodec {
+    max_frame_size: usize,
+    allow_rsv1: bool,
+    /// Whether a fragmented data message is in progress on the receiving side.
+    fragmented: bool,
+}
+
+impl Default for WsFrameCodec {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            allow_rsv1: false,
+            fragmented: false,
+        }
+    }
+}
+
+impl WsFrameCodec {
+    /// Create a codec with the default frame size limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the payload of a single received frame. Defaults to [`DEFAULT_MAX_FRAME_SIZE`].
+    pub fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Accept the RSV1 bit on the first frame of data messages, as used by
+    /// `permessage-deflate`. Defaults to `false`.
+    ///
+    /// The codec does not decompress payloads, frames with RSV1 set are passed through as is.
+    pub fn allow_rsv1(mut self, allow: bool) -> Self {
+        self.allow_rsv1 = allow;
+        self
+    }
+}
+
+impl Decoder for WsFrameCodec {
+    type Item = Frame;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, FrameError> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+
+        let first = src[0];
+        let second = src[1];
+
+        let fin = first & FIN != 0;
+        let rsv1 = first & RSV1 != 0;
+        let opcode =
+            OpCode::from_u8(first & OPCODE).ok_or(FrameError::InvalidOpCode(first & OPCODE))?;
+
+        if first & (RSV2 | RSV3) != 0
+            || (rsv1 && (!self.allow_rsv1 || opcode.is_control() || opcode == OpCode::Continuation))
+        {
+            return Err(FrameError::ReservedBits);
+        }
+
+        if second & MASK == 0 {
+            return Err(FrameError::UnmaskedFrame);
+        }
+
+        if opcode.is_control() {
+            if !fin {
+                return Err(FrameError::FragmentedControlFrame);
+            }
+            if usize::from(second & PAYLOAD_LEN) > MAX_CONTROL_PAYLOAD {
+                return Err(FrameError::ControlFrameTooLarge);
+            }
+        } else {
+            match (opcode, self.fragmented) {
+                (OpCode::Continuation, false) => return Err(FrameError::UnexpectedContinuation),
+                (OpCode::Text | OpCode::Binary, true) => {
+                    return Err(FrameError::ExpectedContinuation);
+                }
+                _ => {}
+            }
+        }
+
+        let (payload_len, header_len) = match second & PAYLOAD_LEN {
+            126 => {
+                if src.len() < 4 {
+                    return Ok(None);
+                }
+                let len = u16::from_be_bytes([src[2], src[3]]);
+                if len < 126 {
+                    return Err(FrameError::InvalidPayloadLength);
+                }
+                (u64::from(len), 4)
+            }
+            127 => {
+                if src.len() < 10 {
+                    return Ok(None);
+                }
+                let mut len = [0; 8];
+                len.copy_from_slice(&src[2..10]);
+                let len = u64::from_be_bytes(len);
+                if len <= u64::from(u16::MAX) || len >> 63 != 0 {
+                    return Err(FrameError::InvalidPayloadLength);
+                }
+                (len, 10)
+            }
+            len => (u64::from(len), 2),
+        };
+
+        let payload_len = usize::try_from(payload_len)
+            .ok()
+            .filter(|len| *len <= self.max_frame_size)
+            .ok_or(FrameError::FrameTooLarge {
+                size: payload_len,
+                max: self.max_frame_size,
+            })?;
+
+        let frame_len = header_len + 4 + payload_len;
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(header_len);
+        let mut key = [0; 4];
+        src.copy_to_slice(&mut key);
+        let mut payload = src.split_to(payload_len);
+        mask::apply_mask(&mut payload, key);
+
+        if opcode == OpCode::Close {
+            validate_close_payload(&payload)?;
+        }
+
+        if !opcode.is_control() {
+            self.fragmented = !fin;
+        }
+
+        Ok(Some(Frame {
+            fin,
+            rsv1,
+            opcode,
+            payload: payload.freeze(),
+        }))
+    }
+}
+
+impl Encoder<Frame> for WsFrameCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), FrameError> {
+        let len = frame.payload.len();
+
+        if frame.opcode.is_control() {
+            if !frame.fin {
+                return Err(FrameError::FragmentedControlFrame);
+            }
+            if len > MAX_CONTROL_PAYLOAD {
+                return Err(FrameError::ControlFrameTooLarge);
+            }
+        }
+
+        if frame.opcode == OpCode::Close {
+            validate_close_payload(&frame.payload)?;
+        }
+
+        let mut first = frame.opcode.as_u8();
+        if frame.fin {
+            first |= FIN;
+        }
+        if frame.rsv1 {
+            first |= RSV1;
+        }
+
+        dst.reserve(10 + len);
+        dst.put_u8(first);
+        if len < 126 {
+            dst.put_u8(len as u8);
+        } else if let Ok(len) = u16::try_from(len) {
+            dst.put_u8(126);
+            dst.put_u16(len);
+        } else {
+            dst.put_u8(127);
+            dst.put_u64(len as u64);
+        }
+        dst.put_slice(&frame.payload);
+
+        Ok(())
+    }
+}
+
+fn validate_close_payload(payload: &[u8]) -> Result<(), FrameError> {
+    match payload {
+        [] => Ok(()),
+        [_] => Err(FrameError::InvalidClosePayload),
+        [high, low, reason @ ..] => {
+            let code = CloseCode(u16::from_be_bytes([*high, *low]));
+            if !code.is_sendable() {
+                return Err(FrameError::InvalidCloseCode(code.as_u16()));
+            }
+            std::str::from_utf8(reason).map_err(|_| FrameError::InvalidCloseReason)?;
+            Ok(())
+        }
+    }
+}
+
+impl<F> RawSocketUpgrade<F> {
+    /// Finalize upgrading the connection and call the provided callback with a
+    /// [`Framed`] stream and sink of WebSocket frames.
+    ///
+    /// If `permessage-deflate` was agreed on with
+    /// [`RawSocketUpgrade::permessage_deflate`], the codec accepts the RSV1 bit. Decompressing
+    /// the payload of such frames is up to the callback.
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_framed<C, Fut>(self, codec: WsFrameCodec, callback: C) -> Response
+    where
+        C: FnOnce(Framed<TokioIo<Upgraded>, WsFrameCodec>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        let codec = codec.allow_rsv1(self.deflate.is_some());
+        self.on_upgrade(move |socket| callback(Framed::new(socket, codec)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 4] = [0x37, 0xfa, 0x21, 0x3d];
+
+    /// A masked frame with the given first byte, encoding the length minimally.
+    fn masked(first: u8, payload: &[u8]) -> BytesMut {
+        let mut frame = BytesMut::new();
+        frame.put_u8(first);
+        if payload.len() < 126 {
+            frame.put_u8(MASK | payload.len() as u8);
+        } else if let Ok(len) = u16::try_from(payload.len()) {
+            frame.put_u8(MASK | 126);
+            frame.put_u16(len);
+        } else {
+            frame.put_u8(MASK | 127);
+            frame.put_u64(payload.len() as u64);
+        }
+        frame.put_slice(&KEY);
+        frame.extend(payload.iter().zip(KEY.iter().cycle()).map(|(b, k)| b ^ k));
+        frame
+    }
+
+    fn decode(codec: &mut WsFrameCodec, bytes: &[u8]) -> Result<Option<Frame>, FrameError> {
+        codec.decode(&mut BytesMut::from(bytes))
+    }
+
+    fn decode_one(bytes: &[u8]) -> Result<Option<Frame>, FrameError> {
+        decode(&mut WsFrameCodec::new(), bytes)
+    }
+
+    fn encode(frame: Frame) -> BytesMut {
+        let mut dst = BytesMut::new();
+        WsFrameCodec::new().encode(frame, &mut dst).unwrap();
+        dst
+    }
+
+    #[test]
+    fn decodes_masked_text_frame() {
+        let frame = decode_one(&masked(FIN | 0x1, b"Hello")).unwrap().unwrap();
+        assert_eq!(frame, Frame::text("Hello"));
+    }
+
+    #[test]
+    fn rejects_unmasked_frame() {
+        assert!(matches!(
+            decode_one(&[FIN | 0x1, 0x02, b'h', b'i']),
+            Err(FrameError::UnmaskedFrame)
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_opcode() {
+        assert!(matches!(
+            decode_one(&masked(FIN | 0x3, b"")),
+            Err(FrameError::InvalidOpCode(0x3))
+        ));
+    }
+
+    #[test]
+    fn rejects_reserved_bits() {
+        for first in [FIN | RSV2 | 0x1, FIN | RSV3 | 0x2, FIN | RSV1 | 0x1] {
+            assert!(
+                matches!(
+                    decode_one(&masked(first, b"x")),
+                    Err(FrameError::ReservedBits)
+                ),
+                "{first:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn rsv1_is_only_allowed_on_first_data_frame() {
+        let mut codec = WsFrameCodec::new().allow_rsv1(true);
+        let frame = decode(&mut codec, &masked(RSV1 | 0x2, b"x"))
+            .unwrap()
+            .unwrap();
+        assert!(frame.rsv1());
+
+        let mut fragment = masked(FIN | RSV1, b"y");
+        assert!(matches!(
+            codec.decode(&mut fragment),
+            Err(FrameError::ReservedBits)
+        ));
+
+        for opcode in [0x8, 0x9, 0xa] {
+            assert!(matches!(
+                decode(
+                    &mut WsFrameCodec::new().allow_rsv1(true),
+                    &masked(FIN | RSV1 | opcode, b"")
+                ),
+                Err(FrameError::ReservedBits)
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_fragmented_control_frame() {
+        assert!(matches!(
+            decode_one(&masked(0x9, b"ping")),
+            Err(FrameError::FragmentedControlFrame)
+        ));
+    }
+
+    #[test]
+    fn control_payload_is_limited_to_125_bytes() {
+        let frame = decode_one(&masked(FIN | 0x9, &[0; 125])).unwrap().unwrap();
+        assert_eq!(frame.payload().len(), 125);
+
+        assert!(matches!(
+            decode_one(&masked(FIN | 0xa, &[0; 126])),
+            Err(FrameError::ControlFrameTooLarge)
+        ));
+    }
+
+    #[test]
+    fn rejects_non_minimal_lengths() {
+        let mut short = vec![FIN | 0x2, MASK | 126];
+        short.extend_from_slice(&125u16.to_be_bytes());
+        short.extend_from_slice(&KEY);
+        short.extend_from_slice(&[0; 125]);
+        assert!(matches!(
+            decode_one(&short),
+            Err(FrameError::InvalidPayloadLength)
+        ));
+
+        let mut medium = vec![FIN | 0x2, MASK | 127];
+        medium.extend_from_slice(&65535u64.to_be_bytes());
+        assert!(matches!(
+            decode_one(&medium),
+            Err(FrameError::InvalidPayloadLength)
+        ));
+
+        let mut top_bit = vec![FIN | 0x2, MASK | 127];
+        top_bit.extend_from_slice(&(1u64 << 63 | 65536).to_be_bytes());
+        assert!(matches!(
+            decode_one(&top_bit),
+            Err(FrameError::InvalidPayloadLength)
+        ));
+    }
+
+    #[test]
+    fn decodes_extended_lengths() {
+        for len in [126, 65535, 65536] {
+            let payload = vec![0xab; len];
+            let frame = decode_one(&masked(FIN | 0x2, &payload)).unwrap().unwrap();
+            assert_eq!(frame.payload().as_ref(), payload, "{len}");
+        }
+    }
+
+    #[test]
+    fn rejects_frame_over_limit() {
+        let mut codec = WsFrameCodec::new().max_frame_size(4);
+        assert!(
+            decode(&mut codec, &masked(FIN | 0x2, b"four"))
+                .unwrap()
+                .is_some()
+        );
+
+        // The limit applies as soon as the header is read.
+        let header = &masked(FIN | 0x2, b"fives")[..2];
+        assert!(matches!(
+            decode(&mut codec, header),
+            Err(FrameError::FrameTooLarge { size: 5, max: 4 })
+        ));
+    }
+
+    #[test]
+    fn checks_continuation_order() {
+        assert!(matches!(
+            decode_one(&masked(FIN, b"x")),
+            Err(FrameError::UnexpectedContinuation)
+        ));
+
+        let mut codec = WsFrameCodec::new();
+        let first = decode(&mut codec, &masked(0x1, b"a")).unwrap().unwrap();
+        assert!(!first.is_final());
+
+        // Control frames may be interleaved with fragments.
+        let ping = decode(&mut codec, &masked(FIN | 0x9, b""))
+            .unwrap()
+            .unwrap();
+        assert_eq!(ping.opcode(), OpCode::Ping);
+
+        assert!(matches!(
+            decode(&mut codec, &masked(FIN | 0x2, b"b")),
+            Err(FrameError::ExpectedContinuation)
+        ));
+
+        let last = decode(&mut codec, &masked(FIN, b"b")).unwrap().unwrap();
+        assert_eq!(last.opcode(), OpCode::Continuation);
+        assert!(last.is_final());
+
+        assert!(
+            decode(&mut codec, &masked(FIN | 0x1, b"c"))
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn validates_close_payload() {
+        let close = decode_one(&masked(FIN | 0x8, b"")).unwrap().unwrap();
+        assert_eq!(close.close_reason(), None);
+
+        let mut payload = 1000u16.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"bye");
+        let close = decode_one(&masked(FIN | 0x8, &payload)).unwrap().unwrap();
+        assert_eq!(close.close_reason(), Some((CloseCode::NORMAL, "bye")));
+
+        assert!(matches!(
+            decode_one(&masked(FIN | 0x8, &[0x03])),
+            Err(FrameError::InvalidClosePayload)
+        ));
+
+        for code in [999u16, 1005, 1006] {
+            assert!(
+                matches!(
+                    decode_one(&masked(FIN | 0x8, &code.to_be_bytes())),
+                    Err(FrameError::InvalidCloseCode(invalid)) if invalid == code
+                ),
+                "{code}"
+            );
+        }
+
+        let mut payload = 1000u16.to_be_bytes().to_vec();
+        payload.extend_from_slice(&[0xff, 0xfe]);
+        let error = decode_one(&masked(FIN | 0x8, &payload)).unwrap_err();
+        assert!(matches!(error, FrameError::InvalidCloseReason));
+        assert_eq!(error.close_code(), CloseCode::INVALID_PAYLOAD);
+    }
+
+    #[test]
+    fn partial_input_needs_more_data() {
+        let frame = masked(FIN | 0x2, &[0x55; 300]);
+
+        let mut codec = WsFrameCodec::new();
+        let mut src = BytesMut::new();
+        for byte in &frame[..frame.len() - 1] {
+            src.put_u8(*byte);
+            assert!(codec.decode(&mut src).unwrap().is_none());
+        }
+
+        src.put_u8(frame[frame.len() - 1]);
+        let decoded = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(decoded.payload().as_ref(), [0x55; 300]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn decodes_consecutive_frames() {
+        let mut src = masked(FIN | 0x1, b"one");
+        src.extend_from_slice(&masked(FIN | 0x1, b"two"));
+
+        let mut codec = WsFrameCodec::new();
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(Frame::text("one")));
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(Frame::text("two")));
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+    }
+
+    #[test]
+    fn encodes_length_boundaries() {
+        for (len, header) in [
+            (0, &[0x82, 0][..]),
+            (125, &[0x82, 125]),
+            (126, &[0x82, 126, 0, 126]),
+            (65535, &[0x82, 126, 0xff, 0xff]),
+            (65536, &[0x82, 127, 0, 0, 0, 0, 0, 1, 0, 0]),
+        ] {
+            let encoded = encode(Frame::binary(vec![0; len]));
+            assert_eq!(&encoded[..header.len()], header, "{len}");
+            assert_eq!(encoded.len(), header.len() + len, "{len}");
+        }
+    }
+
+    #[test]
+    fn encodes_flags_unmasked() {
+        let encoded = encode(Frame::new(false, OpCode::Text, "hi").with_rsv1(true));
+        assert_eq!(encoded.as_ref(), [RSV1 | 0x1, 2, b'h', b'i']);
+
+        let encoded = encode(Frame::close(CloseCode::GOING_AWAY, ""));
+        assert_eq!(encoded.as_ref(), [FIN | 0x8, 2, 0x03, 0xe9]);
+    }
+
+    #[test]
+    fn encoder_rejects_invalid_control_frames() {
+        let mut dst = BytesMut::new();
+        let mut codec = WsFrameCodec::new();
+
+        assert!(matches!(
+            codec.encode(Frame::new(false, OpCode::Ping, ""), &mut dst),
+            Err(FrameError::FragmentedControlFrame)
+        ));
+        assert!(matches!(
+            codec.encode(Frame::pong(vec![0; 126]), &mut dst),
+            Err(FrameError::ControlFrameTooLarge)
+        ));
+        assert!(matches!(
+            codec.encode(Frame::close(CloseCode::new(1005), ""), &mut dst),
+            Err(FrameError::InvalidCloseCode(1005))
+        ));
+        assert!(dst.is_empty());
+    }
+}
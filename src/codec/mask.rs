@@ -0,0 +1,180 @@
+//! Masking and unmasking of frame payloads.
+//!
+//! Every frame sent by a client is masked with a 4 byte key, so unmasking is on the hot path of
+//! every received frame. [`apply_mask`] processes 16 or 32 bytes at a time with SIMD instructions
+//! where the CPU supports them and falls back to [`apply_mask_scalar`] otherwise.
+
+/// Mask or unmask `buf` in place with `key`, using the fastest implementation available on the
+/// current CPU.
+///
+/// On x86_64 AVX2 is used if it is detected at runtime and SSE2 otherwise. On aarch64 NEON is
+/// used. All other targets use [`apply_mask_scalar`].
+pub fn apply_mask(buf: &mut [u8], key: [u8; 4]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was checked above.
+            unsafe { x86_64::apply_mask_avx2(buf, key) }
+        } else {
+            // SAFETY: SSE2 is part of the x86_64 baseline.
+            unsafe { x86_64::apply_mask_sse2(buf, key) }
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        // SAFETY: NEON is part of the aarch64 baseline.
+        unsafe { aarch64::apply_mask_neon(buf, key) }
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    apply_mask_scalar(buf, key);
+}
+
+/// Mask or unmask `buf` in place with `key`, 8 bytes at a time without any SIMD instructions.
+pub fn apply_mask_scalar(buf: &mut [u8], key: [u8; 4]) {
+    let mask = u64::from_ne_bytes([
+        key[0], key[1], key[2], key[3], key[0], key[1], key[2], key[3],
+    ]);
+
+    let mut chunks = buf.chunks_exact_mut(8);
+    for chunk in &mut chunks {
+        let word = u64::from_ne_bytes(chunk.try_into().expect("chunk has 8 bytes")) ^ mask;
+        chunk.copy_from_slice(&word.to_ne_bytes());
+    }
+
+    // The remainder starts at a multiple of 8, so the key is not shifted.
+    for (i, byte) in chunks.into_remainder().iter_mut().enumerate() {
+        *byte ^= key[i & 3];
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64 {
+    use super::apply_mask_scalar;
+    use std::arch::x86_64::{
+        __m128i, __m256i, _mm_loadu_si128, _mm_set1_epi32, _mm_storeu_si128, _mm_xor_si128,
+        _mm256_loadu_si256, _mm256_set1_epi32, _mm256_storeu_si256, _mm256_xor_si256,
+    };
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn apply_mask_avx2(buf: &mut [u8], key: [u8; 4]) {
+        let mask = _mm256_set1_epi32(i32::from_ne_bytes(key));
+
+        let mut chunks = buf.chunks_exact_mut(32);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr().cast::<__m256i>();
+            // SAFETY: `chunk` is 32 bytes long and the unaligned load and store variants are used.
+            unsafe { _mm256_storeu_si256(ptr, _mm256_xor_si256(_mm256_loadu_si256(ptr), mask)) };
+        }
+
+        // SAFETY: SSE2 is part of the x86_64 baseline.
+        unsafe { apply_mask_sse2(chunks.into_remainder(), key) }
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn apply_mask_sse2(buf: &mut [u8], key: [u8; 4]) {
+        let mask = _mm_set1_epi32(i32::from_ne_bytes(key));
+
+        let mut chunks = buf.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr().cast::<__m128i>();
+            // SAFETY: `chunk` is 16 bytes long and the unaligned load and store variants are used.
+            unsafe { _mm_storeu_si128(ptr, _mm_xor_si128(_mm_loadu_si128(ptr), mask)) };
+        }
+
+        // The remainder starts at a multiple of 16, so the key is not shifted.
+        apply_mask_scalar(chunks.into_remainder(), key);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use super::apply_mask_scalar;
+    use std::arch::aarch64::{vdupq_n_u32, veorq_u8, vld1q_u8, vreinterpretq_u8_u32, vst1q_u8};
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn apply_mask_neon(buf: &mut [u8], key: [u8; 4]) {
+        let mask = vreinterpretq_u8_u32(vdupq_n_u32(u32::from_ne_bytes(key)));
+
+        let mut chunks = buf.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr();
+            // SAFETY: `chunk` is 16 bytes long and NEON loads and stores have no alignment
+            // requirements.
+            unsafe { vst1q_u8(ptr, veorq_u8(vld1q_u8(ptr), mask)) };
+        }
+
+        // The remainder starts at a multiple of 16, so the key is not shifted.
+        apply_mask_scalar(chunks.into_remainder(), key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 4] = [0x6d, 0xb6, 0xb2, 0x80];
+    const LARGE: [usize; 4] = [1023, 4096, 65537, 1 << 20];
+
+    fn apply_mask_bytewise(buf: &mut [u8], key: [u8; 4]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte ^= key[i & 3];
+        }
+    }
+
+    /// Compare `mask` against byte-at-a-time XOR for short and large buffers starting at
+    /// unaligned offsets.
+    fn check(mask: impl Fn(&mut [u8], [u8; 4])) {
+        for len in (0..=100).chain(LARGE) {
+            for offset in 0..4 {
+                let data: Vec<u8> = (0..len + offset).map(|i| (i * 7 + 3) as u8).collect();
+
+                let mut expected = data.clone();
+                apply_mask_bytewise(&mut expected[offset..], KEY);
+
+                let mut actual = data.clone();
+                mask(&mut actual[offset..], KEY);
+                assert_eq!(actual, expected, "len {len}, offset {offset}");
+
+                // Masking twice restores the input.
+                mask(&mut actual[offset..], KEY);
+                assert_eq!(actual, data, "len {len}, offset {offset}");
+            }
+        }
+    }
+
+    #[test]
+    fn apply_mask_matches_bytewise() {
+        check(apply_mask);
+    }
+
+    #[test]
+    fn apply_mask_scalar_matches_bytewise() {
+        check(apply_mask_scalar);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn apply_mask_sse2_matches_bytewise() {
+        // SAFETY: SSE2 is part of the x86_64 baseline.
+        check(|buf, key| unsafe { x86_64::apply_mask_sse2(buf, key) });
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn apply_mask_avx2_matches_bytewise() {
+        if !std::is_x86_feature_detected!("avx2") {
+            return;
+        }
+        // SAFETY: AVX2 support was checked above.
+        check(|buf, key| unsafe { x86_64::apply_mask_avx2(buf, key) });
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn apply_mask_neon_matches_bytewise() {
+        // SAFETY: NEON is part of the aarch64 baseline.
+        check(|buf, key| unsafe { aarch64::apply_mask_neon(buf, key) });
+    }
+}
@@ -0,0 +1,463 @@
+use super::{CloseCode, Frame, FrameError, OpCode, WsFrameCodec};
+use crate::{OnFailedUpgrade, RawSocketUpgrade};
+use axum::response::Response;
+use bytes::{Bytes, BytesMut};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::fmt;
+use std::future::Future;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+/// The default limit for a complete message, 64 MiB.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 << 20;
+
+/// A complete WebSocket message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A text message, guaranteed to be valid UTF-8.
+    Text(String),
+    /// A binary message.
+    Binary(Bytes),
+    /// A ping with its application data.
+    Ping(Bytes),
+    /// A pong with its application data.
+    Pong(Bytes),
+    /// A close message with an optional status code and reason.
+    Close(Option<(CloseCode, String)>),
+}
+
+impl Message {
+    fn into_frame(self) -> Frame {
+        match self {
+            Self::Text(text) => Frame::text(text),
+            Self::Binary(data) => Frame::binary(data),
+            Self::Ping(data) => Frame::ping(data),
+            Self::Pong(data) => Frame::pong(data),
+            Self::Close(Some((code, reason))) => Frame::close(code, &reason),
+            Self::Close(None) => Frame::close_empty(),
+        }
+    }
+}
+
+/// An error while decoding or encoding messages.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MessageError {
+    /// The underlying frame was invalid or the socket failed.
+    Frame(FrameError),
+    /// A text message was not valid UTF-8.
+    InvalidUtf8,
+    /// The message exceeded the configured limit.
+    MessageTooLarge {
+        /// The size of the message received so far.
+        size: usize,
+        /// The configured limit.
+        max: usize,
+    },
+}
+
+impl MessageError {
+    /// The close code to send to the peer in response to this error.
+    ///
+    /// `1007` for invalid UTF-8, `1009` for messages that are too large and the close code of the
+    /// frame error otherwise.
+    pub fn close_code(&self) -> CloseCode {
+        match self {
+            Self::Frame(err) => err.close_code(),
+            Self::InvalidUtf8 => CloseCode::INVALID_PAYLOAD,
+            Self::MessageTooLarge { .. } => CloseCode::MESSAGE_TOO_BIG,
+        }
+    }
+
+    /// The close message to send to the peer in response to this error.
+    pub fn close_message(&self) -> Message {
+        Message::Close(Some((self.close_code(), String::new())))
+    }
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Frame(err) => fmt::Display::fmt(err, f),
+            Self::InvalidUtf8 => f.write_str("text message is not valid UTF-8"),
+            Self::MessageTooLarge { size, max } => {
+                write!(f, "message of {size} bytes exceeds limit of {max} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MessageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Frame(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FrameError> for MessageError {
+    fn from(err: FrameError) -> Self {
+        Self::Frame(err)
+    }
+}
+
+impl From<std::io::Error> for MessageError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Frame(FrameError::Io(err))
+    }
+}
+
+/// A fragmented message that has not been completed yet.
+#[derive(Debug)]
+struct Partial {
+    text: bool,
+    payload: BytesMut,
+    /// The length of the prefix of `payload` that is known to be valid UTF-8.
+    valid_up_to: usize,
+}
+
+/// A server side [`Decoder`] and [`Encoder`] for complete WebSocket messages on top of
+/// [`WsFrameCodec`].
+///
+/// Fragmented data frames are joined into a single message while control frames received in
+/// between are returned right away. Text messages are validated incrementally, so invalid UTF-8
+/// is detected as soon as the offending fragment arrives.
+#[derive(Debug)]
+pub struct WsMessageCodec {
+    frames: WsFrameCodec,
+    max_message_size: usize,
+    partial: Option<Partial>,
+}
+
+impl Default for WsMessageCodec {
+    fn default() -> Self {
+        Self {
+            frames: WsFrameCodec::default(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            partial: None,
+        }
+    }
+}
+
+impl WsMessageCodec {
+    /// Create a codec with the default size limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the size of a complete message. Defaults to [`DEFAULT_MAX_MESSAGE_SIZE`].
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Limit the payload of a single frame.
+    ///
+    /// See [`WsFrameCodec::max_frame_size`].
+    pub fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.frames = self.frames.max_frame_size(max_frame_size);
+        self
+    }
+
+    fn push(&mut self, frame: Frame) -> Result<Option<Message>, MessageError> {
+        let fin = frame.is_final();
+        let payload = frame.into_payload();
+
+        let partial = self.partial.get_or_insert_with(|| Partial {
+            text: false,
+            payload: BytesMut::new(),
+            valid_up_to: 0,
+        });
+
+        let size = partial.payload.len() + payload.len();
+        if size > self.max_message_size {
+            return Err(MessageError::MessageTooLarge {
+                size,
+                max: self.max_message_size,
+            });
+        }
+        partial.payload.extend_from_slice(&payload);
+
+        if partial.text {
+            match std::str::from_utf8(&partial.payload[partial.valid_up_to..]) {
+                Ok(_) => partial.valid_up_to = partial.payload.len(),
+                // An incomplete code point at the end may be completed by the next fragment.
+                Err(err) if err.error_len().is_none() && !fin => {
+                    partial.valid_up_to += err.valid_up_to();
+                }
+                Err(_) => return Err(MessageError::InvalidUtf8),
+            }
+        }
+
+        if !fin {
+            return Ok(None);
+        }
+
+        let partial = self
+            .partial
+            .take()
+            .expect("partial message was just inserted");
+        let payload = partial.payload.freeze();
+        if partial.text {
+            debug_assert_eq!(partial.valid_up_to, payload.len());
+            // SAFETY: the whole payload has been validated incrementally above, a final fragment
+            // ending in an incomplete code point is rejected.
+            let text = unsafe { String::from_utf8_unchecked(payload.into()) };
+            Ok(Some(Message::Text(text)))
+        } else {
+            Ok(Some(Message::Binary(payload)))
+        }
+    }
+}
+
+impl Decoder for WsMessageCodec {
+    type Item = Message;
+    type Error = MessageError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, MessageError> {
+        while let Some(frame) = self.frames.decode(src)? {
+            let message = match frame.opcode() {
+                OpCode::Ping => Message::Ping(frame.into_payload()),
+                OpCode::Pong => Message::Pong(frame.into_payload()),
+                OpCode::Close => Message::Close(
+                    frame
+                        .close_reason()
+                        .map(|(code, reason)| (code, reason.to_owned())),
+                ),
+                OpCode::Text | OpCode::Binary => {
+                    self.partial = Some(Partial {
+                        text: frame.opcode() == OpCode::Text,
+                        payload: BytesMut::new(),
+                        valid_up_to: 0,
+                    });
+                    match self.push(frame)? {
+                        Some(message) => message,
+                        None => continue,
+                    }
+                }
+                OpCode::Continuation => match self.push(frame)? {
+                    Some(message) => message,
+                    None => continue,
+                },
+            };
+
+            return Ok(Some(message));
+        }
+
+        Ok(None)
+    }
+}
+
+impl Encoder<Message> for WsMessageCodec {
+    type Error = MessageError;
+
+    fn encode(&mut self, message: Message, dst: &mut BytesMut) -> Result<(), MessageError> {
+        Ok(self.frames.encode(message.into_frame(), dst)?)
+    }
+}
+
+impl<F> RawSocketUpgrade<F> {
+    /// Finalize upgrading the connection and call the provided callback with a [`Framed`]
+    /// stream and sink of complete WebSocket messages.
+    ///
+    /// The message codec does not implement `permessage-deflate`, so compression negotiated with
+    /// [`RawSocketUpgrade::permessage_deflate`] is dropped from the response.
+    ///
+    /// ```
+    /// use axum::response::Response;
+    /// use axum_raw_websocket::RawSocketUpgrade;
+    /// use axum_raw_websocket::codec::{Message, WsMessageCodec};
+    /// use futures_util::{SinkExt, StreamExt};
+    ///
+    /// async fn handler(upgrade: RawSocketUpgrade) -> Response {
+    ///     let codec = WsMessageCodec::new().max_message_size(1 << 20);
+    ///     upgrade.on_upgrade_messages(codec, |mut messages| async move {
+    ///         while let Some(message) = messages.next().await {
+    ///             let reply = match message {
+    ///                 Ok(Message::Ping(data)) => Message::Pong(data),
+    ///                 Ok(Message::Close(_)) => Message::Close(None),
+    ///                 Ok(message) => message,
+    ///                 Err(err) => err.close_message(),
+    ///             };
+    ///             if messages.send(reply).await.is_err() {
+    ///                 break;
+    ///             }
+    ///         }
+    ///     })
+    /// }
+    /// ```
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_messages<C, Fut>(mut self, codec: WsMessageCodec, callback: C) -> Response
+    where
+        C: FnOnce(Framed<TokioIo<Upgraded>, WsMessageCodec>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        self.deflate = None;
+        self.on_upgrade(move |socket| callback(Framed::new(socket, codec)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BufMut;
+
+    const KEY: [u8; 4] = [0x01, 0x80, 0x7f, 0xff];
+
+    /// A masked frame as sent by a client.
+    fn frame(fin: bool, opcode: u8, payload: &[u8]) -> BytesMut {
+        assert!(payload.len() < 126);
+        let mut frame = BytesMut::new();
+        frame.put_u8(if fin { 0x80 | opcode } else { opcode });
+        frame.put_u8(0x80 | payload.len() as u8);
+        frame.put_slice(&KEY);
+        frame.extend(payload.iter().zip(KEY.iter().cycle()).map(|(b, k)| b ^ k));
+        frame
+    }
+
+    /// Decode `frames` one after another and collect the results.
+    fn decode_all(
+        codec: &mut WsMessageCodec,
+        frames: &[BytesMut],
+    ) -> Vec<Result<Option<Message>, MessageError>> {
+        let mut src = BytesMut::new();
+        frames
+            .iter()
+            .map(|frame| {
+                src.extend_from_slice(frame);
+                codec.decode(&mut src)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn joins_fragments() {
+        let results = decode_all(
+            &mut WsMessageCodec::new(),
+            &[
+                frame(false, 0x2, b"ab"),
+                frame(false, 0x0, b""),
+                frame(true, 0x0, b"cd"),
+            ],
+        );
+
+        assert!(matches!(results[0], Ok(None)));
+        assert!(matches!(results[1], Ok(None)));
+        assert!(matches!(&results[2], Ok(Some(Message::Binary(data))) if data.as_ref() == b"abcd"));
+    }
+
+    #[test]
+    fn code_point_may_span_fragments() {
+        let euro = "\u{20ac}".as_bytes();
+        let results = decode_all(
+            &mut WsMessageCodec::new(),
+            &[
+                frame(false, 0x1, &[b'x', euro[0]]),
+                frame(false, 0x0, &euro[1..2]),
+                frame(true, 0x0, &euro[2..]),
+            ],
+        );
+
+        assert!(matches!(results[0], Ok(None)));
+        assert!(matches!(results[1], Ok(None)));
+        assert!(matches!(&results[2], Ok(Some(Message::Text(text))) if text == "x\u{20ac}"));
+    }
+
+    #[test]
+    fn invalid_utf8_is_detected_before_fin() {
+        let results = decode_all(
+            &mut WsMessageCodec::new(),
+            &[frame(false, 0x1, b"ok"), frame(false, 0x0, &[0xff])],
+        );
+
+        assert!(matches!(results[0], Ok(None)));
+        let error = results.into_iter().nth(1).unwrap().unwrap_err();
+        assert!(matches!(error, MessageError::InvalidUtf8));
+        assert_eq!(error.close_code(), CloseCode::INVALID_PAYLOAD);
+    }
+
+    #[test]
+    fn truncated_code_point_at_fin_is_invalid() {
+        let euro = "\u{20ac}".as_bytes();
+
+        let results = decode_all(&mut WsMessageCodec::new(), &[frame(true, 0x1, &euro[..2])]);
+        assert!(matches!(results[0], Err(MessageError::InvalidUtf8)));
+
+        let results = decode_all(
+            &mut WsMessageCodec::new(),
+            &[frame(false, 0x1, &euro[..1]), frame(true, 0x0, &euro[1..2])],
+        );
+        assert!(matches!(results[0], Ok(None)));
+        assert!(matches!(results[1], Err(MessageError::InvalidUtf8)));
+    }
+
+    #[test]
+    fn control_frames_are_returned_between_fragments() {
+        let results = decode_all(
+            &mut WsMessageCodec::new(),
+            &[
+                frame(false, 0x1, b"hel"),
+                frame(true, 0x9, b"are you there"),
+                frame(true, 0x0, b"lo"),
+            ],
+        );
+
+        assert!(matches!(results[0], Ok(None)));
+        assert!(
+            matches!(&results[1], Ok(Some(Message::Ping(data))) if data.as_ref() == b"are you there")
+        );
+        assert!(matches!(&results[2], Ok(Some(Message::Text(text))) if text == "hello"));
+    }
+
+    #[test]
+    fn message_size_is_limited() {
+        let mut codec = WsMessageCodec::new().max_message_size(4);
+        let results = decode_all(
+            &mut codec,
+            &[frame(false, 0x2, b"abc"), frame(true, 0x0, b"de")],
+        );
+
+        assert!(matches!(results[0], Ok(None)));
+        let error = results.into_iter().nth(1).unwrap().unwrap_err();
+        assert!(matches!(
+            error,
+            MessageError::MessageTooLarge { size: 5, max: 4 }
+        ));
+        assert_eq!(error.close_code(), CloseCode::MESSAGE_TOO_BIG);
+    }
+
+    #[test]
+    fn decodes_close_reason() {
+        let mut payload = 1001u16.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"bye");
+        let results = decode_all(&mut WsMessageCodec::new(), &[frame(true, 0x8, &payload)]);
+
+        assert!(matches!(
+            &results[0],
+            Ok(Some(Message::Close(Some((CloseCode::GOING_AWAY, reason))))) if reason == "bye"
+        ));
+    }
+
+    #[test]
+    fn frame_errors_are_passed_on() {
+        let results = decode_all(&mut WsMessageCodec::new(), &[frame(true, 0x0, b"x")]);
+        let error = results.into_iter().next().unwrap().unwrap_err();
+
+        assert!(matches!(
+            error,
+            MessageError::Frame(FrameError::UnexpectedContinuation)
+        ));
+        assert_eq!(error.close_code(), CloseCode::PROTOCOL_ERROR);
+    }
+
+    #[test]
+    fn encodes_messages_as_single_frames() {
+        let mut dst = BytesMut::new();
+        WsMessageCodec::new()
+            .encode(Message::Text("hi".to_owned()), &mut dst)
+            .unwrap();
+        assert_eq!(dst.as_ref(), [0x81, 2, b'h', b'i']);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{OnFailedUpgrade, RawSocketUpgrade};
+use axum::response::Response;
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+/// The upgraded connection implementing `futures::io::{AsyncRead, AsyncWrite}` instead of the
+/// tokio traits.
+pub type FuturesIoSocket = Compat<TokioIo<Upgraded>>;
+
+impl<F> RawSocketUpgrade<F> {
+    /// Finalize upgrading the connection and call the provided callback with a stream
+    /// implementing the `futures-io` traits, for libraries that are not built on tokio.
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_futures_io<C, Fut>(self, callback: C) -> Response
+    where
+        C: FnOnce(FuturesIoSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        self.on_upgrade(move |socket| callback(socket.compat()))
+    }
+}
+
+#[cfg(feature = "soketto")]
+mod soketto_adapter {
+    use super::FuturesIoSocket;
+    use crate::{DeflateParams, OnFailedUpgrade, RawSocketUpgrade};
+    use axum::http::HeaderValue;
+    use axum::response::Response;
+    use soketto::connection::{Builder, Mode};
+    use soketto::extension::Param;
+    use soketto::extension::deflate::Deflate;
+    use std::future::Future;
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    impl<F> RawSocketUpgrade<F> {
+        /// Finalize upgrading the connection and call the provided callback with a soketto
+        /// connection [`Builder`] in the server role.
+        ///
+        /// The handshake has already been completed by this crate, so the builder is ready to be
+        /// [`finish`](Builder::finish)ed. If `permessage-deflate` was agreed on with
+        /// [`RawSocketUpgrade::permessage_deflate`], soketto's deflate extension is configured
+        /// with the negotiated parameters. The callback also receives the subprotocol selected by
+        /// [`RawSocketUpgrade::protocols`].
+        ///
+        /// soketto can not compress with a window smaller than 9 bits. If the negotiation settled
+        /// on a smaller server window, or soketto rejects the parameters otherwise, compression is
+        /// dropped from the response. soketto also
+        /// decompresses every message on its own, so `client_no_context_takeover` is always added
+        /// to the response.
+        #[must_use = "to set up the WebSocket connection, this response must be returned"]
+        pub fn on_upgrade_soketto<C, Fut>(mut self, callback: C) -> Response
+        where
+            C: FnOnce(Builder<FuturesIoSocket>, Option<HeaderValue>) -> Fut + Send + 'static,
+            Fut: Future<Output = ()> + Send + 'static,
+            F: OnFailedUpgrade,
+        {
+            let deflate = self
+                .deflate
+                .filter(|deflate| deflate.server_max_window_bits() >= 9)
+                .map(DeflateParams::with_client_no_context_takeover);
+            // Configure the extension before the response goes out, so it only advertises
+            // compression soketto actually performs.
+            let extension = deflate.as_ref().and_then(soketto_deflate);
+            self.deflate = deflate.filter(|_| extension.is_some());
+
+            let protocol = self.protocol.clone();
+
+            self.on_upgrade(move |socket| {
+                let mut builder = Builder::new(socket.compat(), Mode::Server);
+                if let Some(extension) = extension {
+                    builder.add_extensions([Box::new(extension) as Box<_>]);
+                }
+
+                callback(builder, protocol)
+            })
+        }
+    }
+
+    /// soketto's deflate extension configured with `deflate`, `None` if soketto does not accept
+    /// the parameters.
+    fn soketto_deflate(deflate: &DeflateParams) -> Option<Deflate> {
+        let params: Vec<Param<'static>> = deflate
+            .to_extension()
+            .params()
+            .iter()
+            .map(|param| {
+                let mut soketto_param = Param::new(param.name().to_owned());
+                soketto_param.set_value(param.value().map(str::to_owned));
+                soketto_param
+            })
+            .collect();
+
+        let mut extension = Deflate::new(Mode::Server);
+        soketto::extension::Extension::configure(&mut extension, &params).ok()?;
+        Some(extension)
+    }
+}
+
+#[cfg(feature = "async-tungstenite")]
+mod async_tungstenite_adapter {
+    use super::FuturesIoSocket;
+    use crate::{OnFailedUpgrade, RawSocketUpgrade};
+    use async_tungstenite::WebSocketStream;
+    use async_tungstenite::tungstenite::protocol::{Role, WebSocketConfig};
+    use axum::http::HeaderValue;
+    use axum::response::Response;
+    use std::future::Future;
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    impl<F> RawSocketUpgrade<F> {
+        /// Finalize upgrading the connection and call the provided callback with an
+        /// async-tungstenite [`WebSocketStream`] in the server role.
+        ///
+        /// The stream is built with `WebSocketStream::from_raw_socket`, like the tokio-tungstenite
+        /// adapter of the `tungstenite` feature, but on top of the `futures-io` traits. The
+        /// callback also receives the subprotocol selected by [`RawSocketUpgrade::protocols`].
+        ///
+        /// tungstenite does not implement `permessage-deflate`, so compression agreed on with
+        /// [`RawSocketUpgrade::permessage_deflate`] or the layer is dropped from the response.
+        #[must_use = "to set up the WebSocket connection, this response must be returned"]
+        pub fn on_upgrade_async_tungstenite<C, Fut>(
+            mut self,
+            config: WebSocketConfig,
+            callback: C,
+        ) -> Response
+        where
+            C: FnOnce(WebSocketStream<FuturesIoSocket>, Option<HeaderValue>) -> Fut
+                + Send
+                + 'static,
+            Fut: Future<Output = ()> + Send + 'static,
+            F: OnFailedUpgrade,
+        {
+            // tungstenite does not implement any extensions.
+            self.deflate = None;
+
+            let protocol = self.protocol.clone();
+            self.on_upgrade(move |socket| async move {
+                let ws =
+                    WebSocketStream::from_raw_socket(socket.compat(), Role::Server, Some(config))
+                        .await;
+                callback(ws, protocol).await;
+            })
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use crate::WebSocketExtension;
+
+/// The extension token of the per-message deflate extension.
+pub(crate) const PERMESSAGE_DEFLATE: &str = "permessage-deflate";
+
+const SERVER_NO_CONTEXT_TAKEOVER: &str = "server_no_context_takeover";
+const CLIENT_NO_CONTEXT_TAKEOVER: &str = "client_no_context_takeover";
+const SERVER_MAX_WINDOW_BITS: &str = "server_max_window_bits";
+const CLIENT_MAX_WINDOW_BITS: &str = "client_max_window_bits";
+
+const MIN_WINDOW_BITS: u8 = 8;
+const MAX_WINDOW_BITS: u8 = 15;
+
+/// Server side configuration for negotiating the `permessage-deflate` extension as specified in
+/// [RFC 7692](https://datatracker.ietf.org/doc/html/rfc7692).
+///
+/// The crate only takes part in the handshake, compressing and decompressing messages is left to
+/// the library that is driving the raw socket. The negotiated [`DeflateParams`] tell that library
+/// how to configure itself.
+///
+/// See [`RawSocketUpgrade::permessage_deflate`](crate::RawSocketUpgrade::permessage_deflate).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeflateConfig {
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+    server_max_window_bits: u8,
+    client_max_window_bits: u8,
+}
+
+impl Default for DeflateConfig {
+    fn default() -> Self {
+        Self {
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+            server_max_window_bits: MAX_WINDOW_BITS,
+            client_max_window_bits: MAX_WINDOW_BITS,
+        }
+    }
+}
+
+impl DeflateConfig {
+    /// Create a config that accepts every valid offer with the largest possible window.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Always reset the compression context of the server after each message, even if the client
+    /// did not ask for it.
+    pub fn server_no_context_takeover(mut self, enabled: bool) -> Self {
+        self.server_no_context_takeover = enabled;
+        self
+    }
+
+    /// Ask the client to reset its compression context after each message.
+    pub fn client_no_context_takeover(mut self, enabled: bool) -> Self {
+        self.client_no_context_takeover = enabled;
+        self
+    }
+
+    /// The largest LZ77 window the server is willing to compress with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not within `8..=15`.
+    pub fn server_max_window_bits(mut self, bits: u8) -> Self {
+        assert!(
+            (MIN_WINDOW_BITS..=MAX_WINDOW_BITS).contains(&bits),
+            "window bits must be within 8..=15"
+        );
+        self.server_max_window_bits = bits;
+        self
+    }
+
+    /// The largest LZ77 window the client may compress with.
+    ///
+    /// This limit can only be communicated if the client announced support for it by sending
+    /// `client_max_window_bits` in its offer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not within `8..=15`.
+    pub fn client_max_window_bits(mut self, bits: u8) -> Self {
+        assert!(
+            (MIN_WINDOW_BITS..=MAX_WINDOW_BITS).contains(&bits),
+            "window bits must be within 8..=15"
+        );
+        self.client_max_window_bits = bits;
+        self
+    }
+
+    /// Pick the first acceptable `permessage-deflate` offer and compute the response parameters.
+    ///
+    /// Offers with unknown, duplicate or malformed parameters are declined as required by
+    /// RFC 7692 section 5.
+    pub(crate) fn negotiate(&self, offers: &[WebSocketExtension]) -> Option<DeflateParams> {
+        offers
+            .iter()
+            .filter(|offer| offer.name().eq_ignore_ascii_case(PERMESSAGE_DEFLATE))
+            .find_map(|offer| self.accept(offer))
+    }
+
+    fn accept(&self, offer: &WebSocketExtension) -> Option<DeflateParams> {
+        let mut server_no_context_takeover = self.server_no_context_takeover;
+        let mut server_max_window_bits = None;
+        let mut client_max_window_bits = None;
+        let mut seen = Vec::with_capacity(offer.params().len());
+
+        for param in offer.params() {
+            let name = param.name().to_ascii_lowercase();
+            if seen.contains(&name) {
+                return None;
+            }
+
+            match (name.as_str(), param.value()) {
+                (SERVER_NO_CONTEXT_TAKEOVER, None) => server_no_context_takeover = true,
+                (CLIENT_NO_CONTEXT_TAKEOVER, None) => {}
+                (SERVER_MAX_WINDOW_BITS, Some(value)) => {
+                    server_max_window_bits = Some(parse_window_bits(value)?);
+                }
+                (CLIENT_MAX_WINDOW_BITS, None) => client_max_window_bits = Some(MAX_WINDOW_BITS),
+                (CLIENT_MAX_WINDOW_BITS, Some(value)) => {
+                    client_max_window_bits = Some(parse_window_bits(value)?);
+                }
+                _ => return None,
+            }
+
+            seen.push(name);
+        }
+
+        let server_max_window_bits = match server_max_window_bits {
+            Some(bits) => Some(bits.min(self.server_max_window_bits)),
+            None if self.server_max_window_bits < MAX_WINDOW_BITS => {
+                Some(self.server_max_window_bits)
+            }
+            None => None,
+        };
+
+        // A limit for the client can only be sent if the client announced support for it.
+        let client_max_window_bits = client_max_window_bits
+            .map(|bits| bits.min(self.client_max_window_bits))
+            .filter(|bits| *bits < MAX_WINDOW_BITS);
+
+        Some(DeflateParams {
+            server_no_context_takeover,
+            client_no_context_takeover: self.client_no_context_takeover,
+            server_max_window_bits,
+            client_max_window_bits,
+        })
+    }
+}
+
+/// The `permessage-deflate` parameters agreed on during the handshake.
+///
+/// These are sent back to the client in the `Sec-WebSocket-Extensions` response header and must
+/// be used to configure compression on the raw socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeflateParams {
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+    server_max_window_bits: Option<u8>,
+    client_max_window_bits: Option<u8>,
+}
+
+impl DeflateParams {
+    /// Whether the server must reset its compression context after each message.
+    pub fn server_no_context_takeover(&self) -> bool {
+        self.server_no_context_takeover
+    }
+
+    /// Whether the client resets its compression context after each message.
+    pub fn client_no_context_takeover(&self) -> bool {
+        self.client_no_context_takeover
+    }
+
+    /// The LZ77 window size the server compresses with, `15` unless limited during negotiation.
+    pub fn server_max_window_bits(&self) -> u8 {
+        self.server_max_window_bits.unwrap_or(MAX_WINDOW_BITS)
+    }
+
+    /// The LZ77 window size the client compresses with, `15` unless limited during negotiation.
+    pub fn client_max_window_bits(&self) -> u8 {
+        self.client_max_window_bits.unwrap_or(MAX_WINDOW_BITS)
+    }
+
+    /// Require the client to reset its compression context after each message, for
+    /// implementations that can only decompress every message on its own.
+    ///
+    /// The server may always send `client_no_context_takeover`, see
+    /// [RFC 7692 section 7.1.1.2](https://datatracker.ietf.org/doc/html/rfc7692#section-7.1.1.2).
+    #[cfg(feature = "soketto")]
+    pub(crate) fn with_client_no_context_takeover(mut self) -> Self {
+        self.client_no_context_takeover = true;
+        self
+    }
+
+    /// The extension as it is sent in the `Sec-WebSocket-Extensions` response header.
+    pub fn to_extension(&self) -> WebSocketExtension {
+        let mut extension = WebSocketExtension::new(PERMESSAGE_DEFLATE);
+        if self.server_no_context_takeover {
+            extension = extension.with_param(SERVER_NO_CONTEXT_TAKEOVER, None::<String>);
+        }
+        if self.client_no_context_takeover {
+            extension = extension.with_param(CLIENT_NO_CONTEXT_TAKEOVER, None::<String>);
+        }
+        if let Some(bits) = self.server_max_window_bits {
+            extension = extension.with_param(SERVER_MAX_WINDOW_BITS, Some(bits.to_string()));
+        }
+        if let Some(bits) = self.client_max_window_bits {
+            extension = extension.with_param(CLIENT_MAX_WINDOW_BITS, Some(bits.to_string()));
+        }
+        extension
+    }
+}
+
+fn parse_window_bits(value: &str) -> Option<u8> {
+    // RFC 7692 does not allow leading zeros or signs.
+    if value.starts_with('0') || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    value
+        .parse()
+        .ok()
+        .filter(|bits| (MIN_WINDOW_BITS..=MAX_WINDOW_BITS).contains(bits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(params: &[(&str, Option<&str>)]) -> WebSocketExtension {
+        params.iter().fold(
+            WebSocketExtension::new(PERMESSAGE_DEFLATE),
+            |offer, (name, value)| offer.with_param(*name, *value),
+        )
+    }
+
+    fn negotiate(config: DeflateConfig, offers: &[WebSocketExtension]) -> Option<String> {
+        config
+            .negotiate(offers)
+            .map(|params| params.to_extension().to_string())
+    }
+
+    #[test]
+    fn accepts_plain_offer() {
+        assert_eq!(
+            negotiate(DeflateConfig::new(), &[offer(&[])]).as_deref(),
+            Some("permessage-deflate")
+        );
+    }
+
+    #[test]
+    fn ignores_other_extensions() {
+        let offers = [WebSocketExtension::new("x-webkit-deflate-frame")];
+        assert_eq!(negotiate(DeflateConfig::new(), &offers), None);
+    }
+
+    #[test]
+    fn declines_duplicate_parameters() {
+        let duplicate = offer(&[
+            (SERVER_NO_CONTEXT_TAKEOVER, None),
+            ("Server_No_Context_Takeover", None),
+        ]);
+        assert_eq!(negotiate(DeflateConfig::new(), &[duplicate]), None);
+    }
+
+    #[test]
+    fn declines_unknown_and_malformed_parameters() {
+        for params in [
+            &[("mystery", None)][..],
+            &[(CLIENT_NO_CONTEXT_TAKEOVER, Some("1"))],
+            &[(SERVER_MAX_WINDOW_BITS, None)],
+            &[(SERVER_MAX_WINDOW_BITS, Some("08"))],
+            &[(SERVER_MAX_WINDOW_BITS, Some("+9"))],
+            &[(SERVER_MAX_WINDOW_BITS, Some("7"))],
+            &[(CLIENT_MAX_WINDOW_BITS, Some("16"))],
+        ] {
+            assert_eq!(
+                negotiate(DeflateConfig::new(), &[offer(params)]),
+                None,
+                "{params:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn client_window_limit_needs_announcement() {
+        let config = DeflateConfig::new().client_max_window_bits(10);
+
+        // Without `client_max_window_bits` in the offer the limit can not be sent.
+        assert_eq!(
+            negotiate(config, &[offer(&[])]).as_deref(),
+            Some("permessage-deflate")
+        );
+
+        // A bare `client_max_window_bits` only announces support.
+        let bare = offer(&[(CLIENT_MAX_WINDOW_BITS, None)]);
+        assert_eq!(
+            negotiate(DeflateConfig::new(), std::slice::from_ref(&bare)).as_deref(),
+            Some("permessage-deflate")
+        );
+        assert_eq!(
+            negotiate(config, &[bare]).as_deref(),
+            Some("permessage-deflate; client_max_window_bits=10")
+        );
+
+        // A value is a limit the client already applies.
+        let limited = offer(&[(CLIENT_MAX_WINDOW_BITS, Some("10"))]);
+        assert_eq!(
+            negotiate(DeflateConfig::new(), &[limited]).as_deref(),
+            Some("permessage-deflate; client_max_window_bits=10")
+        );
+
+        let wide = offer(&[(CLIENT_MAX_WINDOW_BITS, Some("12"))]);
+        assert_eq!(
+            negotiate(config, &[wide]).as_deref(),
+            Some("permessage-deflate; client_max_window_bits=10")
+        );
+    }
+
+    #[test]
+    fn server_window_takes_the_smaller_limit() {
+        let config = DeflateConfig::new().server_max_window_bits(10);
+
+        let wide = offer(&[(SERVER_MAX_WINDOW_BITS, Some("12"))]);
+        let params = config.negotiate(&[wide]).unwrap();
+        assert_eq!(params.server_max_window_bits(), 10);
+
+        let narrow = offer(&[(SERVER_MAX_WINDOW_BITS, Some("9"))]);
+        let params = config.negotiate(&[narrow]).unwrap();
+        assert_eq!(params.server_max_window_bits(), 9);
+
+        // The server may always limit its own window.
+        let params = config.negotiate(&[offer(&[])]).unwrap();
+        assert_eq!(params.server_max_window_bits(), 10);
+        assert_eq!(params.client_max_window_bits(), 15);
+    }
+
+    #[test]
+    fn falls_back_to_later_offers() {
+        let offers = [
+            offer(&[(SERVER_MAX_WINDOW_BITS, Some("08"))]),
+            WebSocketExtension::new("x-unknown"),
+            offer(&[(SERVER_NO_CONTEXT_TAKEOVER, None)]),
+        ];
+
+        assert_eq!(
+            negotiate(DeflateConfig::new(), &offers).as_deref(),
+            Some("permessage-deflate; server_no_context_takeover")
+        );
+    }
+
+    #[test]
+    fn response_lists_every_agreed_parameter() {
+        let config = DeflateConfig::new()
+            .client_no_context_takeover(true)
+            .client_max_window_bits(9);
+        let offers = [offer(&[
+            (CLIENT_MAX_WINDOW_BITS, None),
+            (SERVER_NO_CONTEXT_TAKEOVER, None),
+            (SERVER_MAX_WINDOW_BITS, Some("10")),
+        ])];
+
+        assert_eq!(
+            negotiate(config, &offers).as_deref(),
+            Some(
+                "permessage-deflate; server_no_context_takeover; client_no_context_takeover; \
+                 server_max_window_bits=10; client_max_window_bits=9"
+            )
+        );
+    }
+}
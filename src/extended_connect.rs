@@ -0,0 +1,179 @@
+use crate::upgrade::UpgradeProtocol;
+use axum::body::Body;
+use axum::extract::ws::rejection::{ConnectionNotUpgradable, MethodNotConnect};
+use axum::http::{Method, Version, request::Parts};
+use axum::response::Response;
+use std::marker::PhantomData;
+
+/// The set of `:protocol` values accepted by an [`ExtendedConnect`].
+///
+/// ```
+/// use axum::response::Response;
+/// use axum_raw_websocket::{ConnectProtocols, ExtendedConnect, Upgrade};
+///
+/// struct Masque;
+///
+/// impl ConnectProtocols for Masque {
+///     const PROTOCOLS: &'static [&'static str] = &["connect-udp", "connect-ip"];
+/// }
+///
+/// async fn handler(upgrade: Upgrade<ExtendedConnect<Masque>>) -> Response {
+///     upgrade.on_upgrade_with_protocol(|stream, connect| async move {
+///         match connect.protocol() {
+///             "connect-udp" => { /* proxy UDP over `stream` */ }
+///             _ => { /* proxy IP over `stream` */ }
+///         }
+///     })
+/// }
+/// ```
+pub trait ConnectProtocols: Send + 'static {
+    /// The accepted `:protocol` values, compared case-sensitively.
+    const PROTOCOLS: &'static [&'static str];
+}
+
+/// An HTTP/2+ extended `CONNECT` as specified in
+/// [RFC 8441](https://datatracker.ietf.org/doc/html/rfc8441#section-4) for any of the `:protocol`
+/// values listed by `P`.
+///
+/// Use it with the [`Upgrade`](crate::Upgrade) extractor to get the raw h2 stream once the
+/// `200 OK` response has been sent.
+pub struct ExtendedConnect<P> {
+    protocol: hyper::ext::Protocol,
+    _marker: PhantomData<fn() -> P>,
+}
+
+impl<P> std::fmt::Debug for ExtendedConnect<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtendedConnect")
+            .field("protocol", &self.protocol)
+            .finish()
+    }
+}
+
+impl<P> ExtendedConnect<P> {
+    /// The `:protocol` value that was matched.
+    pub fn protocol(&self) -> &str {
+        self.protocol.as_str()
+    }
+}
+
+impl<P> UpgradeProtocol for ExtendedConnect<P>
+where
+    P: ConnectProtocols,
+{
+    type Rejection = ExtendedConnectRejection;
+
+    fn validate(parts: &Parts) -> Result<Self, Self::Rejection> {
+        if parts.method != Method::CONNECT {
+            return Err(MethodNotConnect::default().into());
+        }
+
+        let protocol = parts
+            .extensions
+            .get::<hyper::ext::Protocol>()
+            .filter(|_| parts.version >= Version::HTTP_2)
+            .ok_or(NotExtendedConnect)?;
+
+        if !P::PROTOCOLS.contains(&protocol.as_str()) {
+            return Err(UnsupportedConnectProtocol.into());
+        }
+
+        Ok(Self {
+            protocol: protocol.clone(),
+            _marker: PhantomData,
+        })
+    }
+
+    fn response(&self) -> Response {
+        Response::new(Body::empty())
+    }
+}
+
+define_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "Request is not an HTTP/2 extended CONNECT"]
+    /// Rejection type for [`ExtendedConnect`] used if the request has no `:protocol`
+    /// pseudo-header.
+    pub struct NotExtendedConnect;
+}
+
+define_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "`:protocol` pseudo-header is not supported"]
+    /// Rejection type for [`ExtendedConnect`] used if the `:protocol` pseudo-header is not one of
+    /// the [`ConnectProtocols::PROTOCOLS`].
+    pub struct UnsupportedConnectProtocol;
+}
+
+composite_rejection! {
+    /// Rejection used for [`ExtendedConnect`].
+    ///
+    /// Contains one variant for each way the [`ExtendedConnect`] protocol can fail.
+    pub enum ExtendedConnectRejection {
+        MethodNotConnect,
+        NotExtendedConnect,
+        UnsupportedConnectProtocol,
+        ConnectionNotUpgradable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    struct Masque;
+
+    impl ConnectProtocols for Masque {
+        const PROTOCOLS: &'static [&'static str] = &["connect-udp", "connect-ip"];
+    }
+
+    fn parts(method: Method, protocol: Option<&'static str>) -> Parts {
+        let mut request = Request::builder()
+            .method(method)
+            .version(Version::HTTP_2)
+            .uri("https://example.com/.well-known/masque/udp/192.0.2.6/443/")
+            .body(())
+            .unwrap();
+        if let Some(protocol) = protocol {
+            request
+                .extensions_mut()
+                .insert(hyper::ext::Protocol::from_static(protocol));
+        }
+        request.into_parts().0
+    }
+
+    fn validate(parts: &Parts) -> Result<ExtendedConnect<Masque>, ExtendedConnectRejection> {
+        ExtendedConnect::<Masque>::validate(parts)
+    }
+
+    #[test]
+    fn listed_protocol_is_accepted() {
+        let connect = validate(&parts(Method::CONNECT, Some("connect-ip"))).unwrap();
+        assert_eq!(connect.protocol(), "connect-ip");
+    }
+
+    #[test]
+    fn unlisted_protocol_is_rejected() {
+        assert!(matches!(
+            validate(&parts(Method::CONNECT, Some("websocket"))),
+            Err(ExtendedConnectRejection::UnsupportedConnectProtocol(_))
+        ));
+    }
+
+    #[test]
+    fn plain_connect_is_rejected() {
+        assert!(matches!(
+            validate(&parts(Method::CONNECT, None)),
+            Err(ExtendedConnectRejection::NotExtendedConnect(_))
+        ));
+    }
+
+    #[test]
+    fn get_is_rejected() {
+        assert!(matches!(
+            validate(&parts(Method::GET, Some("connect-udp"))),
+            Err(ExtendedConnectRejection::MethodNotConnect(_))
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+use axum::http::header::{self, HeaderMap};
+use std::fmt;
+
+/// A single extension offered by the client in a `Sec-WebSocket-Extensions` header.
+///
+/// See [RFC 6455 section 9.1](https://datatracker.ietf.org/doc/html/rfc6455#section-9.1) for the
+/// grammar of the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketExtension {
+    name: String,
+    params: Vec<ExtensionParam>,
+}
+
+impl WebSocketExtension {
+    /// Create a new extension without any parameters.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Add a parameter to the extension.
+    pub fn with_param(mut self, name: impl Into<String>, value: Option<impl Into<String>>) -> Self {
+        self.params.push(ExtensionParam {
+            name: name.into(),
+            value: value.map(Into::into),
+        });
+        self
+    }
+
+    /// The extension token, e.g. `permessage-deflate`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// All parameters of the extension in the order they were sent.
+    pub fn params(&self) -> &[ExtensionParam] {
+        &self.params
+    }
+
+    /// Look up a parameter by name.
+    ///
+    /// Returns `None` if the parameter is absent and `Some(None)` if it is present without a
+    /// value.
+    pub fn param(&self, name: &str) -> Option<Option<&str>> {
+        self.params
+            .iter()
+            .find(|param| param.name.eq_ignore_ascii_case(name))
+            .map(ExtensionParam::value)
+    }
+}
+
+impl fmt::Display for WebSocketExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        for param in &self.params {
+            write!(f, "; {param}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A parameter of a [`WebSocketExtension`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionParam {
+    name: String,
+    value: Option<String>,
+}
+
+impl ExtensionParam {
+    /// The parameter name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The parameter value with surrounding quotes removed, if one was given.
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+}
+
+impl fmt::Display for ExtensionParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}={}", self.name, value),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// Parse all `Sec-WebSocket-Protocol` headers of a request into the list of requested
+/// subprotocols.
+///
+/// Header values that are not valid UTF-8 and empty list elements are skipped.
+pub(crate) fn parse_protocols(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|protocol| !protocol.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Parse all `Sec-WebSocket-Extensions` headers of a request.
+///
+/// Header values that are not valid UTF-8 and empty list elements are skipped.
+pub(crate) fn parse_extensions(headers: &HeaderMap) -> Vec<WebSocketExtension> {
+    headers
+        .get_all(header::SEC_WEBSOCKET_EXTENSIONS)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| split_unquoted(value, ','))
+        .filter_map(parse_extension)
+        .collect()
+}
+
+fn parse_extension(value: &str) -> Option<WebSocketExtension> {
+    let mut parts = split_unquoted(value, ';');
+    let name = parts.next()?;
+
+    let params = parts
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) => ExtensionParam {
+                name: name.trim().to_owned(),
+                value: Some(unquote(value.trim()).to_owned()),
+            },
+            None => ExtensionParam {
+                name: param.to_owned(),
+                value: None,
+            },
+        })
+        .collect();
+
+    Some(WebSocketExtension {
+        name: name.to_owned(),
+        params,
+    })
+}
+
+/// Split `value` at `separator`, ignoring separators inside of quoted strings, and trim the
+/// resulting elements.
+fn split_unquoted(value: &str, separator: char) -> impl Iterator<Item = &str> {
+    let mut in_quotes = false;
+    let mut start = 0;
+    let mut parts = Vec::new();
+
+    for (index, c) in value.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c == separator && !in_quotes => {
+                parts.push(value[start..index].trim());
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim());
+
+    parts.into_iter().filter(|part| !part.is_empty())
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(name: header::HeaderName, values: &[&'static [u8]]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(&name, HeaderValue::from_bytes(value).unwrap());
+        }
+        headers
+    }
+
+    fn extensions(values: &[&'static [u8]]) -> Vec<WebSocketExtension> {
+        parse_extensions(&headers(header::SEC_WEBSOCKET_EXTENSIONS, values))
+    }
+
+    #[test]
+    fn split_unquoted_ignores_separators_in_quotes() {
+        let parts: Vec<_> = split_unquoted(r#"a; b="x;y" ;c"#, ';').collect();
+        assert_eq!(parts, ["a", r#"b="x;y""#, "c"]);
+
+        let parts: Vec<_> = split_unquoted(r#"a="1,2", b"#, ',').collect();
+        assert_eq!(parts, [r#"a="1,2""#, "b"]);
+    }
+
+    #[test]
+    fn split_unquoted_skips_empty_elements() {
+        let parts: Vec<_> = split_unquoted(" , a,, ,b, ", ',').collect();
+        assert_eq!(parts, ["a", "b"]);
+
+        assert_eq!(split_unquoted("", ',').count(), 0);
+    }
+
+    #[test]
+    fn parses_extensions_with_params() {
+        let parsed = extensions(&[
+            b"permessage-deflate; client_max_window_bits, permessage-deflate; server_max_window_bits=10",
+        ]);
+
+        assert_eq!(
+            parsed,
+            [
+                WebSocketExtension::new("permessage-deflate")
+                    .with_param("client_max_window_bits", None::<String>),
+                WebSocketExtension::new("permessage-deflate")
+                    .with_param("server_max_window_bits", Some("10")),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_values_keep_commas_and_semicolons() {
+        let parsed = extensions(&[br#"foo; a="x,y"; b="p;q", bar"#]);
+
+        assert_eq!(
+            parsed,
+            [
+                WebSocketExtension::new("foo")
+                    .with_param("a", Some("x,y"))
+                    .with_param("b", Some("p;q")),
+                WebSocketExtension::new("bar"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_every_header_and_skips_invalid_utf8() {
+        let parsed = extensions(&[b"foo", b"\xff\xfe", b"bar;, ,baz"]);
+
+        let names: Vec<_> = parsed.iter().map(WebSocketExtension::name).collect();
+        assert_eq!(names, ["foo", "bar", "baz"]);
+        assert!(parsed[1].params().is_empty());
+    }
+
+    #[test]
+    fn parses_protocols_from_every_header() {
+        let parsed = parse_protocols(&headers(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            &[b"chat, superchat", b"\xff", b" ,v2.chat"],
+        ));
+
+        assert_eq!(parsed, ["chat", "superchat", "v2.chat"]);
+    }
+}
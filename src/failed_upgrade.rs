@@ -0,0 +1,87 @@
+use crate::{Handshake, OnFailedUpgrade};
+use axum::Error;
+use axum::http::{HeaderMap, Uri};
+use std::future::Future;
+use std::net::SocketAddr;
+
+/// The request a failed upgrade belongs to, passed to callbacks registered with
+/// [`RawSocketUpgrade::on_failed_upgrade_async`](crate::RawSocketUpgrade::on_failed_upgrade_async).
+#[derive(Debug, Clone)]
+pub struct FailedUpgradeContext {
+    uri: Uri,
+    headers: HeaderMap,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl FailedUpgradeContext {
+    pub(crate) fn from_handshake(handshake: &Handshake) -> Self {
+        Self {
+            uri: handshake.uri().clone(),
+            headers: handshake.headers().clone(),
+            peer_addr: handshake.peer_addr(),
+        }
+    }
+
+    /// The request URI.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The request headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The address of the peer, available if the app is served with
+    /// [`into_make_service_with_connect_info`](axum::Router::into_make_service_with_connect_info).
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+}
+
+/// An [`OnFailedUpgrade`] running an async callback with the [`FailedUpgradeContext`] of the
+/// request.
+///
+/// Created by
+/// [`RawSocketUpgrade::on_failed_upgrade_async`](crate::RawSocketUpgrade::on_failed_upgrade_async).
+///
+/// The upgrade task awaits the callback, so it runs wherever the
+/// [`Spawner`](crate::Spawner) put the task. Only [`OnFailedUpgrade::call`], which is not used by
+/// this crate, spawns it with [`tokio::spawn`] instead.
+pub struct AsyncOnFailedUpgrade<C> {
+    callback: C,
+    context: FailedUpgradeContext,
+}
+
+impl<C> AsyncOnFailedUpgrade<C> {
+    pub(crate) fn new(callback: C, context: FailedUpgradeContext) -> Self {
+        Self { callback, context }
+    }
+}
+
+impl<C> std::fmt::Debug for AsyncOnFailedUpgrade<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncOnFailedUpgrade")
+            .field("context", &self.context)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, Fut> OnFailedUpgrade for AsyncOnFailedUpgrade<C>
+where
+    C: FnOnce(Error, FailedUpgradeContext) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    /// Spawn the callback onto the tokio runtime with [`tokio::spawn`].
+    ///
+    /// This ignores the [`Spawner`](crate::Spawner) of the upgrade, which is not known here. The
+    /// upgrade task awaits [`OnFailedUpgrade::call_async`] instead, this is only used when the
+    /// callback is invoked by hand.
+    fn call(self, error: Error) {
+        tokio::spawn((self.callback)(error, self.context));
+    }
+
+    fn call_async(self, error: Error) -> impl Future<Output = ()> + Send {
+        (self.callback)(error, self.context)
+    }
+}
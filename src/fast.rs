@@ -0,0 +1,128 @@
+use crate::{OnFailedUpgrade, RawSocketUpgrade};
+use axum::response::Response;
+use fastwebsockets::{FragmentCollector, Role, WebSocket};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+
+/// Configuration of the [`WebSocket`] handed out by [`RawSocketUpgrade::on_upgrade_fast`].
+///
+/// The defaults match the ones of fastwebsockets.
+#[derive(Debug, Clone, Copy)]
+pub struct FastWebSocketConfig {
+    max_message_size: usize,
+    auto_pong: bool,
+    auto_close: bool,
+    writev: bool,
+}
+
+impl Default for FastWebSocketConfig {
+    fn default() -> Self {
+        Self {
+            max_message_size: 64 << 20,
+            auto_pong: true,
+            auto_close: true,
+            writev: true,
+        }
+    }
+}
+
+impl FastWebSocketConfig {
+    /// Create the default config.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The largest message the socket accepts in bytes. Defaults to 64 MiB.
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Whether pings are answered automatically. Defaults to `true`.
+    pub fn auto_pong(mut self, auto_pong: bool) -> Self {
+        self.auto_pong = auto_pong;
+        self
+    }
+
+    /// Whether close frames are answered automatically. Defaults to `true`.
+    pub fn auto_close(mut self, auto_close: bool) -> Self {
+        self.auto_close = auto_close;
+        self
+    }
+
+    /// Whether vectored writes are used for large frames. Defaults to `true`.
+    pub fn writev(mut self, writev: bool) -> Self {
+        self.writev = writev;
+        self
+    }
+
+    fn apply<S>(&self, ws: &mut WebSocket<S>) {
+        ws.set_max_message_size(self.max_message_size);
+        ws.set_auto_pong(self.auto_pong);
+        ws.set_auto_close(self.auto_close);
+        ws.set_writev(self.writev);
+    }
+}
+
+impl<F> RawSocketUpgrade<F> {
+    /// Finalize upgrading the connection and call the provided callback with a fastwebsockets
+    /// [`FragmentCollector`] that reassembles fragmented messages.
+    ///
+    /// fastwebsockets does not implement `permessage-deflate`, so compression negotiated with
+    /// [`RawSocketUpgrade::permessage_deflate`] is dropped from the response and the connection
+    /// continues uncompressed.
+    ///
+    /// ```
+    /// use axum::response::Response;
+    /// use axum_raw_websocket::{FastWebSocketConfig, RawSocketUpgrade};
+    /// use fastwebsockets::OpCode;
+    ///
+    /// async fn handler(upgrade: RawSocketUpgrade) -> Response {
+    ///     let config = FastWebSocketConfig::new().max_message_size(1 << 20);
+    ///     upgrade.on_upgrade_fast(config, |mut ws| async move {
+    ///         while let Ok(frame) = ws.read_frame().await {
+    ///             match frame.opcode {
+    ///                 OpCode::Close => break,
+    ///                 OpCode::Text | OpCode::Binary => {
+    ///                     if ws.write_frame(frame).await.is_err() {
+    ///                         break;
+    ///                     }
+    ///                 }
+    ///                 _ => {}
+    ///             }
+    ///         }
+    ///     })
+    /// }
+    /// ```
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_fast<C, Fut>(self, config: FastWebSocketConfig, callback: C) -> Response
+    where
+        C: FnOnce(FragmentCollector<TokioIo<Upgraded>>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        self.on_upgrade_fast_websocket(config, move |ws| callback(FragmentCollector::new(ws)))
+    }
+
+    /// Like [`RawSocketUpgrade::on_upgrade_fast`] but hands out the plain fastwebsockets
+    /// [`WebSocket`], which yields individual frames.
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_fast_websocket<C, Fut>(
+        mut self,
+        config: FastWebSocketConfig,
+        callback: C,
+    ) -> Response
+    where
+        C: FnOnce(WebSocket<TokioIo<Upgraded>>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        self.deflate = None;
+        self.on_upgrade(move |socket| {
+            let mut ws = WebSocket::after_handshake(socket, Role::Server);
+            config.apply(&mut ws);
+            callback(ws)
+        })
+    }
+}
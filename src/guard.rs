@@ -0,0 +1,104 @@
+use crate::{DefaultOnFailedUpgrade, OnFailedUpgrade, RawSocketUpgrade};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+
+impl<F> RawSocketUpgrade<F> {
+    /// Run an async check, e.g. authentication, before the upgrade is accepted.
+    ///
+    /// The guard sees the request [`Parts`], including extensions set by earlier layers such as
+    /// sessions or [`Extension`](axum::Extension)s, and either returns an identity, which is
+    /// handed to the callback of [`GuardedUpgrade::on_upgrade`], or a rejection that is returned
+    /// to the client instead of the `101 Switching Protocols` response.
+    ///
+    /// ```
+    /// use axum::http::StatusCode;
+    /// use axum::response::Response;
+    /// use axum_raw_websocket::RawSocketUpgrade;
+    ///
+    /// struct User(String);
+    ///
+    /// async fn handler(upgrade: RawSocketUpgrade) -> Result<Response, Response> {
+    ///     let upgrade = upgrade
+    ///         .guard(async |parts| match parts.uri.query() {
+    ///             Some(query) if query.starts_with("token=") => Ok(User(query[6..].to_owned())),
+    ///             _ => Err(StatusCode::UNAUTHORIZED),
+    ///         })
+    ///         .await?;
+    ///
+    ///     Ok(upgrade.on_upgrade(|socket, User(name)| async move {
+    ///         // ...
+    ///     }))
+    /// }
+    ///
+    /// let app: axum::Router = axum::Router::new().route("/ws", axum::routing::any(handler));
+    /// ```
+    pub async fn guard<G, I, R>(mut self, guard: G) -> Result<GuardedUpgrade<I, F>, Response>
+    where
+        G: AsyncFnOnce(&Parts) -> Result<I, R>,
+        R: IntoResponse,
+    {
+        let parts = self
+            .handshake
+            .to_parts(std::mem::take(&mut self.extensions));
+        let result = guard(&parts).await;
+        self.extensions = parts.extensions;
+
+        match result {
+            Ok(identity) => Ok(GuardedUpgrade {
+                upgrade: self,
+                identity,
+            }),
+            Err(rejection) => Err(rejection.into_response()),
+        }
+    }
+}
+
+/// A [`RawSocketUpgrade`] that passed [`RawSocketUpgrade::guard`], together with the identity
+/// returned by the guard.
+pub struct GuardedUpgrade<I, F = DefaultOnFailedUpgrade> {
+    upgrade: RawSocketUpgrade<F>,
+    identity: I,
+}
+
+impl<I, F> std::fmt::Debug for GuardedUpgrade<I, F>
+where
+    I: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GuardedUpgrade")
+            .field("upgrade", &self.upgrade)
+            .field("identity", &self.identity)
+            .finish()
+    }
+}
+
+impl<I, F> GuardedUpgrade<I, F> {
+    /// The identity returned by the guard.
+    pub fn identity(&self) -> &I {
+        &self.identity
+    }
+
+    /// Split into the upgrade and the identity, e.g. to use one of the other `on_upgrade`
+    /// variants of [`RawSocketUpgrade`].
+    pub fn into_parts(self) -> (RawSocketUpgrade<F>, I) {
+        (self.upgrade, self.identity)
+    }
+
+    /// Finalize upgrading the connection and call the provided callback with the stream and the
+    /// identity returned by the guard.
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade<C, Fut>(self, callback: C) -> Response
+    where
+        I: Send + 'static,
+        C: FnOnce(TokioIo<Upgraded>, I) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        let identity = self.identity;
+        self.upgrade
+            .on_upgrade(move |socket| callback(socket, identity))
+    }
+}
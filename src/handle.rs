@@ -0,0 +1,301 @@
+use crate::spawn::UpgradeTask;
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::future::{Future, poll_fn};
+use std::pin::{Pin, pin};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use tokio::sync::{Notify, oneshot, watch};
+
+/// A handle to a connection task spawned by
+/// [`RawSocketUpgrade::on_upgrade_with_handle`](crate::RawSocketUpgrade::on_upgrade_with_handle).
+///
+/// Awaiting the handle yields the output of the callback. Dropping the handle detaches the task,
+/// it keeps running.
+pub struct ConnectionHandle<T> {
+    output: oneshot::Receiver<T>,
+    close: watch::Sender<bool>,
+    state: Arc<TaskState>,
+}
+
+struct TaskState {
+    finished: AtomicBool,
+    aborted: AtomicBool,
+    abort: Notify,
+}
+
+impl<T> std::fmt::Debug for ConnectionHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionHandle")
+            .field("finished", &self.is_finished())
+            .field("closed", &*self.close.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> ConnectionHandle<T> {
+    /// Abort the connection task.
+    ///
+    /// The task is dropped the next time it is polled, which also drops the connection. Awaiting
+    /// the handle afterwards yields [`ConnectionJoinError::Aborted`] unless the callback had
+    /// already completed.
+    pub fn abort(&self) {
+        self.state.aborted.store(true, Ordering::Release);
+        self.state.abort.notify_one();
+    }
+
+    /// Whether the connection task has finished, because the callback completed, the upgrade
+    /// failed or the task was aborted.
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+    }
+
+    /// Ask the callback to close the connection through its [`CloseSignal`].
+    pub fn close(&self) {
+        self.close.send_replace(true);
+    }
+
+    /// Get a [`CloseSignal`] that fires together with the one passed to the callback.
+    pub fn close_signal(&self) -> CloseSignal {
+        CloseSignal {
+            rx: self.close.subscribe(),
+        }
+    }
+
+    /// Wrap the task so it can be aborted and reports when it is finished.
+    pub(crate) fn track(&self, task: UpgradeTask) -> UpgradeTask {
+        let state = self.state.clone();
+        // Created outside of the future, so it is also dropped if the task is never polled.
+        let finished = FinishedGuard(state.clone());
+
+        Box::pin(async move {
+            let _finished = finished;
+            let mut task = pin!(task);
+            let mut aborted = pin!(state.abort.notified());
+
+            poll_fn(|cx| {
+                if aborted.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(());
+                }
+                task.as_mut().poll(cx)
+            })
+            .await;
+        })
+    }
+}
+
+impl<T> Future for ConnectionHandle<T> {
+    type Output = Result<T, ConnectionJoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.output).poll(cx).map(|output| {
+            output.map_err(|_| {
+                if self.state.aborted.load(Ordering::Acquire) {
+                    ConnectionJoinError::Aborted
+                } else {
+                    ConnectionJoinError::Incomplete
+                }
+            })
+        })
+    }
+}
+
+/// Sets [`TaskState::finished`] when the task completes, is aborted, panics or is dropped by the
+/// spawner.
+struct FinishedGuard(Arc<TaskState>);
+
+impl Drop for FinishedGuard {
+    fn drop(&mut self) {
+        self.0.finished.store(true, Ordering::Release);
+    }
+}
+
+/// The error returned by awaiting a [`ConnectionHandle`] if the callback did not produce an
+/// output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConnectionJoinError {
+    /// The task was aborted with [`ConnectionHandle::abort`].
+    Aborted,
+    /// The task ended without running the callback to completion, because the upgrade failed,
+    /// the callback panicked or the spawner dropped the task.
+    Incomplete,
+}
+
+impl std::fmt::Display for ConnectionJoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Aborted => f.write_str("connection task was aborted"),
+            Self::Incomplete => f.write_str("connection task ended without completing"),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionJoinError {}
+
+/// Signal asking the connection callback to close the connection, fired by
+/// [`ConnectionHandle::close`].
+#[derive(Debug, Clone)]
+pub struct CloseSignal {
+    rx: watch::Receiver<bool>,
+}
+
+impl CloseSignal {
+    /// Whether closing the connection has been requested.
+    pub fn is_closed(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Wait until closing the connection is requested.
+    ///
+    /// Never completes if the [`ConnectionHandle`] is dropped without requesting it.
+    pub async fn closed(&self) {
+        let mut rx = self.rx.clone();
+        if rx.wait_for(|closed| *closed).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// The callback side of a [`ConnectionHandle`].
+pub(crate) struct Connection<T> {
+    output: oneshot::Sender<T>,
+    close: CloseSignal,
+}
+
+impl<T> Connection<T> {
+    /// Run `callback` and report its output to the handle.
+    pub(crate) async fn run<C, Fut>(self, socket: TokioIo<Upgraded>, callback: C)
+    where
+        C: FnOnce(TokioIo<Upgraded>, CloseSignal) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let output = callback(socket, self.close).await;
+        let _ = self.output.send(output);
+    }
+}
+
+/// Create a connected [`ConnectionHandle`] and [`Connection`].
+pub(crate) fn channel<T>() -> (ConnectionHandle<T>, Connection<T>) {
+    let (output_tx, output_rx) = oneshot::channel();
+    let (close_tx, close_rx) = watch::channel(false);
+
+    let handle = ConnectionHandle {
+        output: output_rx,
+        close: close_tx,
+        state: Arc::new(TaskState {
+            finished: AtomicBool::new(false),
+            aborted: AtomicBool::new(false),
+            abort: Notify::new(),
+        }),
+    };
+    let connection = Connection {
+        output: output_tx,
+        close: CloseSignal { rx: close_rx },
+    };
+
+    (handle, connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DefaultOnFailedUpgrade;
+    use crate::upgrade::upgrade_task;
+    use axum::http::Request;
+
+    #[tokio::test]
+    async fn output_is_returned() {
+        let (handle, connection) = channel();
+        tokio::spawn(handle.track(Box::pin(async move {
+            let _ = connection.output.send(42);
+        })));
+
+        assert_eq!(handle.await, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn task_dropped_without_poll_is_finished() {
+        let (handle, _connection) = channel::<()>();
+        drop(handle.track(Box::pin(std::future::pending())));
+
+        assert!(handle.is_finished());
+    }
+
+    #[tokio::test]
+    async fn abort_before_first_poll() {
+        let (handle, connection) = channel::<()>();
+        let task = handle.track(Box::pin(async move {
+            std::future::pending::<()>().await;
+            drop(connection);
+        }));
+
+        handle.abort();
+        tokio::spawn(task).await.unwrap();
+
+        assert!(handle.is_finished());
+        assert_eq!(handle.await, Err(ConnectionJoinError::Aborted));
+    }
+
+    #[tokio::test]
+    async fn abort_after_first_poll() {
+        let (handle, connection) = channel::<()>();
+        let (started_tx, started_rx) = oneshot::channel();
+        let task = tokio::spawn(handle.track(Box::pin(async move {
+            let _ = started_tx.send(());
+            std::future::pending::<()>().await;
+            drop(connection);
+        })));
+
+        started_rx.await.unwrap();
+        assert!(!handle.is_finished());
+
+        handle.abort();
+        task.await.unwrap();
+
+        assert!(handle.is_finished());
+        assert_eq!(handle.await, Err(ConnectionJoinError::Aborted));
+    }
+
+    #[tokio::test]
+    async fn abort_after_completion_keeps_output() {
+        let (handle, connection) = channel();
+        tokio::spawn(handle.track(Box::pin(async move {
+            let _ = connection.output.send("done");
+        })))
+        .await
+        .unwrap();
+
+        handle.abort();
+        assert_eq!(handle.await, Ok("done"));
+    }
+
+    #[tokio::test]
+    async fn close_signal_fires() {
+        let (handle, connection) = channel::<()>();
+        let close = connection.close;
+        let extra = handle.close_signal();
+        assert!(!close.is_closed());
+
+        let waiting = tokio::spawn(async move { close.closed().await });
+        handle.close();
+
+        waiting.await.unwrap();
+        assert!(extra.is_closed());
+        extra.closed().await;
+    }
+
+    #[tokio::test]
+    async fn failed_upgrade_is_incomplete() {
+        let (handle, connection) = channel();
+        // A request that was not upgraded by hyper fails to upgrade right away.
+        let on_upgrade = hyper::upgrade::on(Request::new(()));
+        let task = upgrade_task(on_upgrade, None, DefaultOnFailedUpgrade, move |socket| {
+            connection.run(socket, |_socket, _close| async { 1 })
+        });
+        tokio::spawn(handle.track(task));
+
+        assert_eq!(handle.await, Err(ConnectionJoinError::Incomplete));
+    }
+}
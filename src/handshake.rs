@@ -0,0 +1,113 @@
+use crate::{DeflateParams, WebSocketExtension};
+use axum::extract::ConnectInfo;
+use axum::http::{
+    Extensions, HeaderMap, HeaderValue, Method, Request, Uri, Version, request::Parts,
+};
+use std::net::SocketAddr;
+
+/// Metadata about the request that initiated a WebSocket connection together with the outcome of
+/// the handshake.
+///
+/// Passed to the callback of
+/// [`RawSocketUpgrade::on_upgrade_with_handshake`](crate::RawSocketUpgrade::on_upgrade_with_handshake)
+/// so handlers don't need to clone request data into the closure by hand.
+#[derive(Debug, Clone)]
+pub struct Handshake {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+    peer_addr: Option<SocketAddr>,
+    protocol: Option<HeaderValue>,
+    deflate: Option<DeflateParams>,
+}
+
+impl Handshake {
+    pub(crate) fn from_parts(parts: &Parts) -> Self {
+        Self {
+            method: parts.method.clone(),
+            uri: parts.uri.clone(),
+            version: parts.version,
+            headers: parts.headers.clone(),
+            peer_addr: parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| *addr),
+            protocol: None,
+            deflate: None,
+        }
+    }
+
+    /// Rebuild the request parts, with `extensions` as the request extensions.
+    pub(crate) fn to_parts(&self, extensions: Extensions) -> Parts {
+        let (mut parts, ()) = Request::new(()).into_parts();
+        parts.method = self.method.clone();
+        parts.uri = self.uri.clone();
+        parts.version = self.version;
+        parts.headers = self.headers.clone();
+        parts.extensions = extensions;
+        parts
+    }
+
+    pub(crate) fn negotiated(
+        mut self,
+        protocol: Option<HeaderValue>,
+        deflate: Option<DeflateParams>,
+    ) -> Self {
+        self.protocol = protocol;
+        self.deflate = deflate;
+        self
+    }
+
+    /// The request method, `GET` for HTTP/1.1 and `CONNECT` for HTTP/2+.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The request URI.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The query string of the request URI, if any.
+    pub fn query(&self) -> Option<&str> {
+        self.uri.query()
+    }
+
+    /// The HTTP version of the request.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The request headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The address of the peer.
+    ///
+    /// Only available if the app is served with
+    /// [`into_make_service_with_connect_info::<SocketAddr>`](axum::Router::into_make_service_with_connect_info).
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The subprotocol selected during the handshake, if any.
+    pub fn protocol(&self) -> Option<&HeaderValue> {
+        self.protocol.as_ref()
+    }
+
+    /// The negotiated `permessage-deflate` parameters, if compression was agreed on.
+    pub fn deflate(&self) -> Option<&DeflateParams> {
+        self.deflate.as_ref()
+    }
+
+    /// All extensions agreed on during the handshake, as sent in the
+    /// `Sec-WebSocket-Extensions` response header.
+    pub fn extensions(&self) -> Vec<WebSocketExtension> {
+        self.deflate
+            .iter()
+            .map(DeflateParams::to_extension)
+            .collect()
+    }
+}
@@ -0,0 +1,271 @@
+use crate::websocket::is_websocket_attempt;
+use crate::{DeflateConfig, OriginPolicy, RawSocketUpgrade, RawSocketUpgradeRejection, Spawner};
+use axum::extract::Request;
+use axum::http::{HeaderMap, header};
+use axum::response::{IntoResponse, Response};
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer applying a shared upgrade policy to every [`RawSocketUpgrade`] extracted below it.
+///
+/// The policy is stored in the request extensions and picked up by
+/// [`RawSocketUpgrade`]'s extractor, so origin checks, subprotocols, compression and timeouts can
+/// be configured once at the [`Router`](axum::Router) level. Handlers can still override every
+/// setting on the extracted [`RawSocketUpgrade`].
+///
+/// WebSocket upgrade requests whose `Origin` is not allowed by the
+/// [`origin_policy`](RawSocketUpgradeLayer::origin_policy) are rejected with
+/// [`RawSocketUpgradeRejection::ForbiddenOrigin`] before they reach the handler. Other requests
+/// pass through untouched.
+///
+/// ```
+/// use axum::{Router, response::Response, routing::any};
+/// use axum_raw_websocket::{OriginPolicy, RawSocketUpgrade, RawSocketUpgradeLayer};
+/// use std::time::Duration;
+///
+/// async fn handler(upgrade: RawSocketUpgrade) -> Response {
+///     upgrade.on_upgrade(|socket| async move {
+///         // ...
+///     })
+/// }
+///
+/// let app: Router = Router::new()
+///     .route("/chat", any(handler))
+///     .route("/feed", any(handler))
+///     .layer(
+///         RawSocketUpgradeLayer::new()
+///             .origin_policy(OriginPolicy::new().allow_exact("https://example.com"))
+///             .protocols(["chat.v2", "chat.v1"])
+///             .upgrade_timeout(Duration::from_secs(10)),
+///     );
+/// ```
+#[derive(Clone, Default)]
+pub struct RawSocketUpgradeLayer {
+    policy: UpgradePolicy,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct UpgradePolicy {
+    origin: Option<OriginPolicy>,
+    protocols: Vec<Cow<'static, str>>,
+    deflate: Option<DeflateConfig>,
+    upgrade_timeout: Option<Duration>,
+    spawner: Option<Arc<dyn Spawner>>,
+    response_headers: HeaderMap,
+    render_rejection: Option<RenderRejection>,
+    pub(crate) strict: bool,
+}
+
+type RenderRejection = Arc<dyn Fn(&RawSocketUpgradeRejection) -> Response + Send + Sync>;
+
+impl std::fmt::Debug for RawSocketUpgradeLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawSocketUpgradeLayer")
+            .field("protocols", &self.policy.protocols)
+            .field("deflate", &self.policy.deflate)
+            .field("upgrade_timeout", &self.policy.upgrade_timeout)
+            .field("response_headers", &self.policy.response_headers)
+            .field("strict", &self.policy.strict)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RawSocketUpgradeLayer {
+    /// Create a layer with an empty policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject WebSocket upgrade requests whose `Origin` is not allowed by `policy`.
+    ///
+    /// See [`RawSocketUpgrade::check_origin`].
+    pub fn origin_policy(mut self, policy: OriginPolicy) -> Self {
+        self.policy.origin = Some(policy);
+        self
+    }
+
+    /// Select a subprotocol from `protocols`.
+    ///
+    /// See [`RawSocketUpgrade::protocols`].
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'static, str>>,
+    {
+        self.policy.protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Negotiate `permessage-deflate` with `config`.
+    ///
+    /// See [`RawSocketUpgrade::permessage_deflate`].
+    pub fn permessage_deflate(mut self, config: DeflateConfig) -> Self {
+        self.policy.deflate = Some(config);
+        self
+    }
+
+    /// Give up on connections that do not complete the upgrade within `timeout`.
+    ///
+    /// See [`RawSocketUpgrade::upgrade_timeout`].
+    pub fn upgrade_timeout(mut self, timeout: Duration) -> Self {
+        self.policy.upgrade_timeout = Some(timeout);
+        self
+    }
+
+    /// Run connection tasks with `spawner`.
+    ///
+    /// See [`RawSocketUpgrade::spawner`].
+    pub fn spawner<S>(mut self, spawner: S) -> Self
+    where
+        S: Spawner,
+    {
+        self.policy.spawner = Some(Arc::new(spawner));
+        self
+    }
+
+    /// Add headers to every upgrade response.
+    ///
+    /// See [`RawSocketUpgrade::with_response_headers`].
+    pub fn with_response_headers(mut self, headers: HeaderMap) -> Self {
+        for (name, value) in &headers {
+            self.policy.response_headers.append(name, value.clone());
+        }
+        self
+    }
+
+    /// Validate handshakes strictly according to
+    /// [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455#section-4.2.1).
+    ///
+    /// On top of the regular checks this
+    ///
+    /// - requires `upgrade` as a token of the comma separated `Connection` list instead of
+    ///   anywhere in the header, rejecting e.g. `Connection: notupgrade` with
+    ///   [`MissingConnectionUpgradeToken`](crate::MissingConnectionUpgradeToken),
+    /// - requires `websocket` as a token of the comma separated `Upgrade` list instead of the
+    ///   whole header, accepting e.g. `Upgrade: websocket, h2c` and rejecting others with
+    ///   [`MissingUpgradeWebSocketToken`](crate::MissingUpgradeWebSocketToken),
+    /// - rejects repeated `Sec-WebSocket-Key` and `Sec-WebSocket-Version` headers with
+    ///   [`DuplicateSecurityHeader`](crate::DuplicateSecurityHeader),
+    /// - requires `Sec-WebSocket-Key` to be a base64 encoded 16 byte nonce for HTTP/1.1,
+    ///   rejecting others with [`InvalidWebSocketKey`](crate::InvalidWebSocketKey),
+    /// - requires exactly one `Host` header holding a host and an optional port for HTTP/1.1,
+    ///   rejecting others with [`InvalidHostHeader`](crate::InvalidHostHeader).
+    pub fn strict(mut self) -> Self {
+        self.policy.strict = true;
+        self
+    }
+
+    /// Render every [`RawSocketUpgradeRejection`] returned by handlers below this layer, as well
+    /// as the layer's own [`ForbiddenOrigin`](crate::ForbiddenOrigin) rejection, with `render`,
+    /// e.g. to return `application/problem+json` bodies:
+    ///
+    /// ```
+    /// use axum_raw_websocket::{RawSocketUpgradeLayer, RawSocketUpgradeRejection};
+    ///
+    /// let layer = RawSocketUpgradeLayer::new().render_rejection(RawSocketUpgradeRejection::problem_json);
+    /// ```
+    pub fn render_rejection<R>(mut self, render: R) -> Self
+    where
+        R: Fn(&RawSocketUpgradeRejection) -> Response + Send + Sync + 'static,
+    {
+        self.policy.render_rejection = Some(Arc::new(render));
+        self
+    }
+}
+
+impl<S> Layer<S> for RawSocketUpgradeLayer {
+    type Service = RawSocketUpgradeService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RawSocketUpgradeService {
+            inner,
+            policy: Arc::new(self.policy.clone()),
+        }
+    }
+}
+
+/// Service created by [`RawSocketUpgradeLayer`].
+#[derive(Clone)]
+pub struct RawSocketUpgradeService<S> {
+    inner: S,
+    policy: Arc<UpgradePolicy>,
+}
+
+impl<S> std::fmt::Debug for RawSocketUpgradeService<S>
+where
+    S: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawSocketUpgradeService")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Service<Request> for RawSocketUpgradeService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        if let Some(origin) = &self.policy.origin
+            && is_websocket_attempt(req.method(), req.headers(), req.extensions())
+            && let Err(rejection) = origin.check(req.headers().get(header::ORIGIN))
+        {
+            let rejection = RawSocketUpgradeRejection::from(rejection);
+            let response = match &self.policy.render_rejection {
+                Some(render) => render(&rejection),
+                None => rejection.into_response(),
+            };
+            return Box::pin(std::future::ready(Ok(response)));
+        }
+
+        req.extensions_mut().insert(self.policy.clone());
+        let response = self.inner.call(req);
+
+        match self.policy.render_rejection.clone() {
+            Some(render) => Box::pin(async move {
+                let response = response.await?;
+                Ok(
+                    match response.extensions().get::<RawSocketUpgradeRejection>() {
+                        Some(rejection) => render(rejection),
+                        None => response,
+                    },
+                )
+            }),
+            None => Box::pin(response),
+        }
+    }
+}
+
+impl UpgradePolicy {
+    /// Apply the policy to a freshly extracted upgrade.
+    pub(crate) fn apply<F>(&self, mut upgrade: RawSocketUpgrade<F>) -> RawSocketUpgrade<F> {
+        if !self.protocols.is_empty() {
+            upgrade = upgrade.protocols(self.protocols.iter().cloned());
+        }
+        if let Some(deflate) = self.deflate {
+            upgrade = upgrade.permessage_deflate(deflate);
+        }
+        if let Some(spawner) = &self.spawner {
+            upgrade.spawner = spawner.clone();
+        }
+        upgrade.upgrade_timeout = self.upgrade_timeout;
+        upgrade.with_response_headers(self.response_headers.clone())
+    }
+}
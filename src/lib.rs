@@ -1,15 +1,7 @@
-use axum::body::Body;
-use axum::extract::FromRequestParts;
-#[cfg(feature = "http2")]
-use axum::extract::ws::rejection::InvalidProtocolPseudoheader;
-use axum::extract::ws::rejection::{
-    ConnectionNotUpgradable, InvalidConnectionHeader, InvalidUpgradeHeader,
-    InvalidWebSocketVersionHeader, MethodNotConnect, MethodNotGet, WebSocketKeyHeaderMissing,
-    WebSocketUpgradeRejection,
-};
+use axum::extract::{FromRequestParts, OptionalFromRequestParts};
 
 use axum::http::{
-    Method, StatusCode, Version,
+    Extensions,
     header::{self, HeaderMap, HeaderName, HeaderValue},
     request::Parts,
 };
@@ -17,8 +9,77 @@ use axum::{Error, body::Bytes, response::Response};
 
 use hyper::upgrade::Upgraded;
 use hyper_util::rt::TokioIo;
+use layer::UpgradePolicy;
 use sha1::{Digest, Sha1};
+use std::borrow::Cow;
 use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[macro_use]
+mod macros;
+
+#[cfg(feature = "codec")]
+pub mod codec;
+#[cfg(feature = "futures-io")]
+mod compat;
+mod deflate;
+#[cfg(feature = "http2")]
+mod extended_connect;
+mod extensions;
+mod failed_upgrade;
+#[cfg(feature = "fastwebsockets")]
+mod fast;
+mod guard;
+mod handle;
+mod handshake;
+mod layer;
+mod origin;
+mod rejection;
+mod spawn;
+mod strict;
+#[cfg(feature = "tungstenite")]
+mod tungstenite;
+mod tunnel;
+mod upgrade;
+mod websocket;
+
+#[cfg(feature = "async-tungstenite")]
+pub use async_tungstenite;
+#[cfg(feature = "futures-io")]
+pub use compat::FuturesIoSocket;
+pub use deflate::{DeflateConfig, DeflateParams};
+#[cfg(feature = "http2")]
+pub use extended_connect::{
+    ConnectProtocols, ExtendedConnect, ExtendedConnectRejection, NotExtendedConnect,
+    UnsupportedConnectProtocol,
+};
+pub use extensions::{ExtensionParam, WebSocketExtension};
+pub use failed_upgrade::{AsyncOnFailedUpgrade, FailedUpgradeContext};
+#[cfg(feature = "fastwebsockets")]
+pub use fast::FastWebSocketConfig;
+#[cfg(feature = "fastwebsockets")]
+pub use fastwebsockets;
+pub use guard::GuardedUpgrade;
+pub use handle::{CloseSignal, ConnectionHandle, ConnectionJoinError};
+pub use handshake::Handshake;
+pub use layer::{RawSocketUpgradeLayer, RawSocketUpgradeService};
+pub use origin::{ForbiddenOrigin, OriginPolicy};
+pub use rejection::{InvalidWebSocketKey, RawSocketUpgradeRejection, UnsupportedWebSocketVersion};
+#[cfg(feature = "soketto")]
+pub use soketto;
+pub use spawn::{Spawner, TokioSpawner, UpgradeTask};
+pub use strict::{
+    DuplicateSecurityHeader, InvalidHostHeader, MissingConnectionUpgradeToken,
+    MissingUpgradeWebSocketToken,
+};
+#[cfg(feature = "tungstenite")]
+pub use tokio_tungstenite;
+pub use tunnel::{
+    ConnectTunnel, ConnectTunnelRejection, ForbiddenTunnelTarget, InvalidConnectAuthority,
+};
+pub use upgrade::{Upgrade, UpgradeProtocol, UpgradeTimeout, requests_upgrade};
+pub use websocket::WebSocketProtocol;
 
 /// This websocket upgrade is based on the axum integrated one
 /// ([axum::extract::ws::WebSocketUpgrade])[https://docs.rs/axum/0.8.3/axum/extract/struct.WebSocketUpgrade.html].
@@ -34,33 +95,282 @@ use std::future::Future;
 /// [`MethodFilter`]: crate::routing::MethodFilter
 #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
 pub struct RawSocketUpgrade<F = DefaultOnFailedUpgrade> {
-    /// `None` if HTTP/2+ WebSockets are used.
-    sec_websocket_key: Option<HeaderValue>,
+    websocket: WebSocketProtocol,
     on_upgrade: hyper::upgrade::OnUpgrade,
     on_failed_upgrade: F,
-    sec_websocket_protocol: Option<HeaderValue>,
+    sec_websocket_protocol: Vec<String>,
+    sec_websocket_extensions: Vec<WebSocketExtension>,
+    origin: Option<HeaderValue>,
+    /// The subprotocol selected by [`RawSocketUpgrade::protocols`], if any.
+    protocol: Option<HeaderValue>,
+    /// The `permessage-deflate` parameters agreed on by [`RawSocketUpgrade::permessage_deflate`].
+    deflate: Option<DeflateParams>,
+    /// Additional headers for the upgrade response.
+    response_headers: HeaderMap,
+    map_response: Option<MapResponse>,
+    handshake: Handshake,
+    /// The request extensions, handed to [`RawSocketUpgrade::guard`].
+    extensions: Extensions,
+    /// How long to wait for the client to complete the upgrade, see
+    /// [`RawSocketUpgrade::upgrade_timeout`].
+    upgrade_timeout: Option<Duration>,
+    /// Runs the connection task, see [`RawSocketUpgrade::spawner`].
+    spawner: Arc<dyn Spawner>,
+    /// Called with errors returned by fallible callbacks, see
+    /// [`RawSocketUpgrade::on_connection_error`].
+    on_connection_error: Option<BoxedOnConnectionError>,
 }
 
+type MapResponse = Box<dyn FnOnce(Response) -> Response + Send>;
+type BoxedOnConnectionError = Box<dyn FnOnce(Error, Handshake) + Send>;
+
 impl<F> std::fmt::Debug for RawSocketUpgrade<F> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RelayUpgrade")
-            .field("sec_websocket_key", &self.sec_websocket_key)
+            .field("sec_websocket_key", &self.websocket.sec_websocket_key())
             .field("sec_websocket_protocol", &self.sec_websocket_protocol)
+            .field("sec_websocket_extensions", &self.sec_websocket_extensions)
+            .field("origin", &self.origin)
+            .field("protocol", &self.protocol)
+            .field("deflate", &self.deflate)
+            .field("response_headers", &self.response_headers)
+            .field("upgrade_timeout", &self.upgrade_timeout)
             .finish_non_exhaustive()
     }
 }
 
 impl<F> RawSocketUpgrade<F> {
+    /// Set the known protocols.
+    ///
+    /// The client's `Sec-WebSocket-Protocol` header is parsed as a comma-separated list and the
+    /// first entry, in the client's order of preference, that is also contained in `protocols`
+    /// is selected. The selected protocol is echoed back in the `Sec-WebSocket-Protocol` header
+    /// of the upgrade response.
+    ///
+    /// If none of the requested protocols is supported, no protocol is selected and the header is
+    /// omitted from the response. It is up to the client to decide whether it wants to continue
+    /// without a subprotocol.
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'static, str>>,
+    {
+        let supported: Vec<Cow<'static, str>> = protocols.into_iter().map(Into::into).collect();
+
+        let selected = self
+            .requested_protocols()
+            .find(|requested| supported.iter().any(|protocol| protocol == requested))
+            .and_then(|protocol| HeaderValue::from_str(protocol).ok());
+        self.protocol = selected;
+
+        self
+    }
+
+    /// Return the selected WebSocket subprotocol, if one has been chosen.
+    ///
+    /// This is only ever `Some` after a successful call to [`RawSocketUpgrade::protocols`].
+    pub fn selected_protocol(&self) -> Option<&HeaderValue> {
+        self.protocol.as_ref()
+    }
+
+    /// The subprotocols requested by the client in the `Sec-WebSocket-Protocol` header(s), in the
+    /// client's order of preference.
+    ///
+    /// Header values that are not valid UTF-8 are skipped.
+    pub fn requested_protocols(&self) -> impl Iterator<Item = &str> {
+        self.sec_websocket_protocol.iter().map(String::as_str)
+    }
+
+    /// The extensions offered by the client in the `Sec-WebSocket-Extensions` header(s), in the
+    /// client's order of preference.
+    pub fn requested_extensions(&self) -> &[WebSocketExtension] {
+        &self.sec_websocket_extensions
+    }
+
+    /// Negotiate the `permessage-deflate` extension with the given server side configuration.
+    ///
+    /// The first acceptable offer from the client's `Sec-WebSocket-Extensions` header is accepted
+    /// and the agreed parameters are sent back in the upgrade response. If the client did not
+    /// offer compression, or none of its offers is acceptable, the connection continues without
+    /// compression.
+    ///
+    /// This crate does not compress anything itself. Use
+    /// [`RawSocketUpgrade::on_upgrade_with_deflate`] to hand the agreed parameters to the
+    /// library that drives the socket.
+    pub fn permessage_deflate(mut self, config: DeflateConfig) -> Self {
+        self.deflate = config.negotiate(&self.sec_websocket_extensions);
+        self
+    }
+
+    /// Return the negotiated `permessage-deflate` parameters, if compression was agreed on.
+    pub fn deflate(&self) -> Option<&DeflateParams> {
+        self.deflate.as_ref()
+    }
+
+    /// The `Origin` header sent by the client, if any.
+    pub fn origin(&self) -> Option<&HeaderValue> {
+        self.origin.as_ref()
+    }
+
+    /// Reject the upgrade unless the request's `Origin` is allowed by `policy`.
+    ///
+    /// This protects against cross-site WebSocket hijacking and should be used by every endpoint
+    /// that relies on cookies or other ambient credentials.
+    ///
+    /// ```
+    /// use axum::response::Response;
+    /// use axum_raw_websocket::{ForbiddenOrigin, OriginPolicy, RawSocketUpgrade};
+    ///
+    /// async fn handler(upgrade: RawSocketUpgrade) -> Result<Response, ForbiddenOrigin> {
+    ///     let policy = OriginPolicy::new().allow_subdomains("https://*.example.com");
+    ///     Ok(upgrade
+    ///         .check_origin(&policy)?
+    ///         .on_upgrade(|socket| async move {
+    ///             // ...
+    ///         }))
+    /// }
+    /// ```
+    pub fn check_origin(self, policy: &OriginPolicy) -> Result<Self, ForbiddenOrigin> {
+        policy.check(self.origin.as_ref())?;
+        Ok(self)
+    }
+
+    /// Add headers to the upgrade response, e.g. `Set-Cookie` or session affinity headers.
+    ///
+    /// The headers are appended after the ones required by the handshake, on the
+    /// `101 Switching Protocols` response for HTTP/1.1 as well as the `200 OK` response for
+    /// HTTP/2+. Multiple calls accumulate. Handshake headers like `Connection`, `Upgrade` or
+    /// `Sec-WebSocket-Accept` must not be set this way.
+    pub fn with_response_headers(mut self, headers: HeaderMap) -> Self {
+        for (name, value) in &headers {
+            self.response_headers.append(name, value.clone());
+        }
+        self
+    }
+
+    /// Modify the upgrade response right before it is returned from
+    /// [`RawSocketUpgrade::on_upgrade`].
+    ///
+    /// The callback runs after all handshake headers and the headers added with
+    /// [`RawSocketUpgrade::with_response_headers`] have been set. Only one callback can be
+    /// registered, a later call replaces the previous one.
+    pub fn map_response<M>(mut self, map: M) -> Self
+    where
+        M: FnOnce(Response) -> Response + Send + 'static,
+    {
+        self.map_response = Some(Box::new(map));
+        self
+    }
+
+    /// Give up on the connection if the client has not completed the upgrade within `timeout`
+    /// after the handshake response was sent.
+    ///
+    /// Without a timeout the spawned task waits for as long as the connection stays open. When
+    /// the timeout fires, the callback registered with [`RawSocketUpgrade::on_failed_upgrade`] is
+    /// called with an [`UpgradeTimeout`] error.
+    pub fn upgrade_timeout(mut self, timeout: Duration) -> Self {
+        self.upgrade_timeout = Some(timeout);
+        self
+    }
+
+    /// Run the connection task with `spawner` instead of [`tokio::spawn`].
+    ///
+    /// The task waits for the upgrade to complete and then drives the `on_upgrade` callback, so
+    /// spawning it onto a [`TaskTracker`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html)
+    /// or a dedicated runtime covers the whole lifetime of the connection. See [`Spawner`].
+    pub fn spawner<S>(mut self, spawner: S) -> Self
+    where
+        S: Spawner,
+    {
+        self.spawner = Arc::new(spawner);
+        self
+    }
+
+    /// Provide a callback to call if a callback passed to
+    /// [`RawSocketUpgrade::on_upgrade_fallible`] returns an error.
+    ///
+    /// The hook runs in the connection task after the callback has returned and receives the
+    /// error together with the [`Handshake`] of the connection, so logging and metrics can see
+    /// every failure that happens after the upgrade in one place. Errors are ignored if no hook
+    /// is set.
+    ///
+    /// ```
+    /// use axum::response::Response;
+    /// use axum_raw_websocket::RawSocketUpgrade;
+    ///
+    /// async fn handler(upgrade: RawSocketUpgrade) -> Response {
+    ///     upgrade
+    ///         .on_connection_error(|error, handshake| {
+    ///             eprintln!("connection from {:?} failed: {error}", handshake.peer_addr());
+    ///         })
+    ///         .on_upgrade_fallible(|socket| async move {
+    ///             // ...
+    ///             Ok::<_, std::io::Error>(())
+    ///         })
+    /// }
+    /// ```
+    pub fn on_connection_error<H>(mut self, hook: H) -> Self
+    where
+        H: FnOnce(Error, Handshake) + Send + 'static,
+    {
+        self.on_connection_error = Some(Box::new(hook));
+        self
+    }
+
+    /// Like [`RawSocketUpgrade::on_failed_upgrade`] but with an async callback that also receives
+    /// the URI, headers and peer address of the request.
+    ///
+    /// The callback is awaited in the connection task.
+    ///
+    /// ```
+    /// use axum::response::Response;
+    /// use axum_raw_websocket::{FailedUpgradeContext, RawSocketUpgrade};
+    ///
+    /// async fn audit(error: axum::Error, context: FailedUpgradeContext) {
+    ///     // write `error`, `context.uri()` and `context.peer_addr()` to the audit log
+    /// }
+    ///
+    /// async fn handler(upgrade: RawSocketUpgrade) -> Response {
+    ///     upgrade
+    ///         .on_failed_upgrade_async(audit)
+    ///         .on_upgrade(|socket| async move {
+    ///             // ...
+    ///         })
+    /// }
+    /// ```
+    pub fn on_failed_upgrade_async<C, Fut>(
+        self,
+        callback: C,
+    ) -> RawSocketUpgrade<AsyncOnFailedUpgrade<C>>
+    where
+        C: FnOnce(Error, FailedUpgradeContext) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let context = FailedUpgradeContext::from_handshake(&self.handshake);
+        self.on_failed_upgrade(AsyncOnFailedUpgrade::new(callback, context))
+    }
+
     #[allow(dead_code)]
     pub fn on_failed_upgrade<C>(self, callback: C) -> RawSocketUpgrade<C>
     where
         C: OnFailedUpgrade,
     {
         RawSocketUpgrade {
-            sec_websocket_key: self.sec_websocket_key,
+            websocket: self.websocket,
             on_upgrade: self.on_upgrade,
             on_failed_upgrade: callback,
             sec_websocket_protocol: self.sec_websocket_protocol,
+            sec_websocket_extensions: self.sec_websocket_extensions,
+            origin: self.origin,
+            protocol: self.protocol,
+            deflate: self.deflate,
+            response_headers: self.response_headers,
+            map_response: self.map_response,
+            handshake: self.handshake,
+            extensions: self.extensions,
+            upgrade_timeout: self.upgrade_timeout,
+            spawner: self.spawner,
+            on_connection_error: self.on_connection_error,
         }
     }
 
@@ -73,42 +383,152 @@ impl<F> RawSocketUpgrade<F> {
         Fut: Future<Output = ()> + Send + 'static,
         F: OnFailedUpgrade,
     {
-        let on_upgrade = self.on_upgrade;
-        let on_failed_upgrade = self.on_failed_upgrade;
-
-        tokio::spawn(async move {
-            let upgraded = match on_upgrade.await {
-                Ok(upgraded) => upgraded,
-                Err(err) => {
-                    on_failed_upgrade.call(Error::new(err));
-                    return;
-                }
-            };
-            let upgraded: TokioIo<Upgraded> = TokioIo::new(upgraded);
-            callback(upgraded).await;
+        let (response, spawner, task) = self.into_task(callback);
+        spawner.spawn(task);
+        response
+    }
+
+    /// Like [`RawSocketUpgrade::on_upgrade`] but also returns a [`ConnectionHandle`] for the
+    /// spawned connection task.
+    ///
+    /// The handle can abort the task, tell whether it has finished and be awaited for the output
+    /// of the callback. The callback receives a [`CloseSignal`] that fires once
+    /// [`ConnectionHandle::close`] is called, so it can close the connection gracefully.
+    ///
+    /// ```
+    /// use axum::response::Response;
+    /// use axum_raw_websocket::RawSocketUpgrade;
+    ///
+    /// async fn handler(upgrade: RawSocketUpgrade) -> Response {
+    ///     let (response, handle) = upgrade.on_upgrade_with_handle(|socket, close| async move {
+    ///         close.closed().await;
+    ///         // send a close frame on `socket`
+    ///     });
+    ///     // keep `handle` to close or abort the connection later
+    ///     # drop(handle);
+    ///     response
+    /// }
+    /// ```
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_with_handle<C, Fut>(
+        self,
+        callback: C,
+    ) -> (Response, ConnectionHandle<Fut::Output>)
+    where
+        C: FnOnce(TokioIo<Upgraded>, CloseSignal) -> Fut + Send + 'static,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        let (handle, connection) = handle::channel();
+        let (response, spawner, task) =
+            self.into_task(move |socket| connection.run(socket, callback));
+        spawner.spawn(handle.track(task));
+        (response, handle)
+    }
+
+    /// Build the upgrade response and the task driving the connection, without spawning it.
+    fn into_task<C, Fut>(self, callback: C) -> (Response, Arc<dyn Spawner>, UpgradeTask)
+    where
+        C: FnOnce(TokioIo<Upgraded>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        let protocol = self.protocol;
+        let extensions = self.deflate.map(|deflate| {
+            HeaderValue::from_str(&deflate.to_extension().to_string())
+                .expect("extension parameters are valid header values")
         });
 
-        if let Some(sec_websocket_key) = &self.sec_websocket_key {
-            // If `sec_websocket_key` was `Some`, we are using HTTP/1.1.
-
-            #[allow(clippy::declare_interior_mutable_const)]
-            const UPGRADE: HeaderValue = HeaderValue::from_static("upgrade");
-            #[allow(clippy::declare_interior_mutable_const)]
-            const WEBSOCKET: HeaderValue = HeaderValue::from_static("websocket");
-
-            Response::builder()
-                .status(StatusCode::SWITCHING_PROTOCOLS)
-                .header(header::CONNECTION, UPGRADE)
-                .header(header::UPGRADE, WEBSOCKET)
-                .header(
-                    header::SEC_WEBSOCKET_ACCEPT,
-                    sign(sec_websocket_key.as_bytes()),
-                )
-                .body(Body::empty())
-                .unwrap()
-        } else {
-            Response::new(Body::empty())
+        let task = upgrade::upgrade_task(
+            self.on_upgrade,
+            self.upgrade_timeout,
+            self.on_failed_upgrade,
+            callback,
+        );
+
+        let mut response = self.websocket.response();
+        let headers = response.headers_mut();
+
+        if let Some(protocol) = protocol {
+            headers.insert(header::SEC_WEBSOCKET_PROTOCOL, protocol);
         }
+
+        if let Some(extensions) = extensions {
+            headers.insert(header::SEC_WEBSOCKET_EXTENSIONS, extensions);
+        }
+
+        for (name, value) in &self.response_headers {
+            headers.append(name, value.clone());
+        }
+
+        if let Some(map_response) = self.map_response {
+            response = map_response(response);
+        }
+
+        (response, self.spawner, task)
+    }
+
+    /// Like [`RawSocketUpgrade::on_upgrade`] but also hands the `permessage-deflate` parameters
+    /// agreed on by [`RawSocketUpgrade::permessage_deflate`] to the callback, so the frame layer
+    /// can enable compression to match the handshake.
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_with_deflate<C, Fut>(self, callback: C) -> Response
+    where
+        C: FnOnce(TokioIo<Upgraded>, Option<DeflateParams>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        let deflate = self.deflate;
+        self.on_upgrade(move |socket| callback(socket, deflate))
+    }
+
+    /// Like [`RawSocketUpgrade::on_upgrade`] but also hands a [`Handshake`] to the callback.
+    ///
+    /// The handshake carries the method, URI, version and headers of the request, the peer
+    /// address if [`ConnectInfo`](axum::extract::ConnectInfo) is available, and the negotiated
+    /// subprotocol and extensions.
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_with_handshake<C, Fut>(self, callback: C) -> Response
+    where
+        C: FnOnce(TokioIo<Upgraded>, Handshake) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        let handshake = self
+            .handshake
+            .clone()
+            .negotiated(self.protocol.clone(), self.deflate);
+        self.on_upgrade(move |socket| callback(socket, handshake))
+    }
+
+    /// Like [`RawSocketUpgrade::on_upgrade`] but for callbacks that return a `Result`.
+    ///
+    /// An error returned by the callback is passed to the hook set with
+    /// [`RawSocketUpgrade::on_connection_error`].
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_fallible<C, Fut, E>(mut self, callback: C) -> Response
+    where
+        C: FnOnce(TokioIo<Upgraded>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<axum::BoxError>,
+        F: OnFailedUpgrade,
+    {
+        let on_connection_error = self.on_connection_error.take().map(|hook| {
+            let handshake = self
+                .handshake
+                .clone()
+                .negotiated(self.protocol.clone(), self.deflate);
+            (hook, handshake)
+        });
+
+        self.on_upgrade(move |socket| async move {
+            if let Err(error) = callback(socket).await
+                && let Some((hook, handshake)) = on_connection_error
+            {
+                hook(Error::new(error), handshake);
+            }
+        })
     }
 }
 
@@ -118,6 +538,17 @@ impl<F> RawSocketUpgrade<F> {
 pub trait OnFailedUpgrade: Send + 'static {
     /// Call the callback.
     fn call(self, error: Error);
+
+    /// Call the callback from the upgrade task, which awaits the returned future.
+    ///
+    /// The default implementation runs [`OnFailedUpgrade::call`].
+    fn call_async(self, error: Error) -> impl Future<Output = ()> + Send
+    where
+        Self: Sized,
+    {
+        self.call(error);
+        std::future::ready(())
+    }
 }
 
 impl<F> OnFailedUpgrade for F
@@ -145,81 +576,82 @@ impl<S> FromRequestParts<S> for RawSocketUpgrade<DefaultOnFailedUpgrade>
 where
     S: Send + Sync,
 {
-    type Rejection = WebSocketUpgradeRejection;
+    type Rejection = RawSocketUpgradeRejection;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let sec_websocket_key = if parts.version <= Version::HTTP_11 {
-            if parts.method != Method::GET {
-                return Err(WebSocketUpgradeRejection::MethodNotGet(
-                    MethodNotGet::default(),
-                ));
-            }
-
-            if !header_contains(&parts.headers, header::CONNECTION, "upgrade") {
-                return Err(WebSocketUpgradeRejection::InvalidConnectionHeader(
-                    InvalidConnectionHeader::default(),
-                ));
-            }
-
-            if !header_eq(&parts.headers, header::UPGRADE, "websocket") {
-                return Err(WebSocketUpgradeRejection::InvalidUpgradeHeader(
-                    InvalidUpgradeHeader::default(),
-                ));
-            }
+        let policy = parts.extensions.get::<Arc<UpgradePolicy>>().cloned();
 
-            Some(
-                parts
-                    .headers
-                    .get(header::SEC_WEBSOCKET_KEY)
-                    .ok_or(WebSocketUpgradeRejection::WebSocketKeyHeaderMissing(
-                        WebSocketKeyHeaderMissing::default(),
-                    ))?
-                    .clone(),
-            )
-        } else {
-            if parts.method != Method::CONNECT {
-                return Err(WebSocketUpgradeRejection::MethodNotConnect(
-                    MethodNotConnect::default(),
-                ));
-            }
+        let websocket = WebSocketProtocol::validate(parts)?;
+        let on_upgrade = upgrade::take_on_upgrade(parts)?;
 
-            // if this feature flag is disabled, we won’t be receiving an HTTP/2 request to begin
-            // with.
-            #[cfg(feature = "http2")]
-            if parts
-                .extensions
-                .get::<hyper::ext::Protocol>()
-                .is_none_or(|p| p.as_str() != "websocket")
-            {
-                return Err(WebSocketUpgradeRejection::InvalidProtocolPseudoheader(
-                    InvalidProtocolPseudoheader::default(),
-                ));
-            }
+        let sec_websocket_protocol = extensions::parse_protocols(&parts.headers);
+        let sec_websocket_extensions = extensions::parse_extensions(&parts.headers);
+        let origin = parts.headers.get(header::ORIGIN).cloned();
+        let handshake = Handshake::from_parts(parts);
+        let extensions = parts.extensions.clone();
 
-            None
+        let upgrade = Self {
+            websocket,
+            on_upgrade,
+            sec_websocket_protocol,
+            sec_websocket_extensions,
+            origin,
+            protocol: None,
+            deflate: None,
+            response_headers: HeaderMap::new(),
+            map_response: None,
+            handshake,
+            extensions,
+            upgrade_timeout: None,
+            spawner: Arc::new(TokioSpawner),
+            on_connection_error: None,
+            on_failed_upgrade: DefaultOnFailedUpgrade,
         };
 
-        if !header_eq(&parts.headers, header::SEC_WEBSOCKET_VERSION, "13") {
-            return Err(WebSocketUpgradeRejection::InvalidWebSocketVersionHeader(
-                InvalidWebSocketVersionHeader::default(),
-            ));
-        }
+        Ok(match policy {
+            Some(policy) => policy.apply(upgrade),
+            None => upgrade,
+        })
+    }
+}
 
-        let on_upgrade = parts
-            .extensions
-            .remove::<hyper::upgrade::OnUpgrade>()
-            .ok_or(WebSocketUpgradeRejection::ConnectionNotUpgradable(
-                ConnectionNotUpgradable::default(),
-            ))?;
+/// Extracts `None` for requests that do not try to open a WebSocket, so one route can serve both
+/// plain HTTP and WebSocket clients.
+///
+/// A request tries to open a WebSocket if its `Upgrade` header mentions `websocket`, or, for
+/// HTTP/2+, if it is an extended `CONNECT` with the `:protocol` pseudo-header set to `websocket`.
+/// Such requests are validated as usual and rejected if the handshake is malformed.
+///
+/// ```
+/// use axum::response::{IntoResponse, Response};
+/// use axum_raw_websocket::RawSocketUpgrade;
+///
+/// async fn handler(upgrade: Option<RawSocketUpgrade>) -> Response {
+///     match upgrade {
+///         Some(upgrade) => upgrade.on_upgrade(|socket| async move {
+///             // ...
+///         }),
+///         None => "use a WebSocket client for live updates".into_response(),
+///     }
+/// }
+/// ```
+impl<S> OptionalFromRequestParts<S> for RawSocketUpgrade<DefaultOnFailedUpgrade>
+where
+    S: Send + Sync,
+{
+    type Rejection = RawSocketUpgradeRejection;
 
-        let sec_websocket_protocol = parts.headers.get(header::SEC_WEBSOCKET_PROTOCOL).cloned();
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        if !websocket::is_websocket_attempt(&parts.method, &parts.headers, &parts.extensions) {
+            return Ok(None);
+        }
 
-        Ok(Self {
-            sec_websocket_key,
-            on_upgrade,
-            sec_websocket_protocol,
-            on_failed_upgrade: DefaultOnFailedUpgrade,
-        })
+        <Self as FromRequestParts<S>>::from_request_parts(parts, state)
+            .await
+            .map(Some)
     }
 }
 
@@ -0,0 +1,173 @@
+use axum::http::HeaderValue;
+use std::fmt;
+use std::sync::Arc;
+
+/// A policy deciding which `Origin`s may open a WebSocket connection.
+///
+/// Browsers attach cookies to WebSocket handshakes regardless of the page that initiates them and
+/// WebSockets are not subject to CORS. Without checking the `Origin` header any website can open a
+/// socket in the name of a logged in user, which is known as cross-site WebSocket hijacking.
+///
+/// A new policy denies every origin; rules are added with the `allow_*` methods and an origin is
+/// accepted as soon as one rule matches. Origins are compared ASCII case-insensitively.
+///
+/// ```
+/// use axum_raw_websocket::OriginPolicy;
+///
+/// let policy = OriginPolicy::new()
+///     .allow_exact("https://example.com")
+///     .allow_subdomains("https://*.example.com")
+///     .allow_fn(|origin| origin.starts_with("http://localhost:"));
+///
+/// assert!(policy.is_allowed(Some("https://example.com")));
+/// assert!(policy.is_allowed(Some("https://app.example.com")));
+/// assert!(policy.is_allowed(Some("http://localhost:3000")));
+/// assert!(!policy.is_allowed(Some("https://evil.com")));
+/// ```
+#[derive(Clone)]
+pub struct OriginPolicy {
+    rules: Vec<OriginRule>,
+    allow_missing: bool,
+}
+
+#[derive(Clone)]
+enum OriginRule {
+    Exact(String),
+    Subdomains { prefix: String, suffix: String },
+    Predicate(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl Default for OriginPolicy {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            allow_missing: true,
+        }
+    }
+}
+
+impl fmt::Debug for OriginPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OriginPolicy")
+            .field("rules", &self.rules)
+            .field("allow_missing", &self.allow_missing)
+            .finish()
+    }
+}
+
+impl fmt::Debug for OriginRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(origin) => f.debug_tuple("Exact").field(origin).finish(),
+            Self::Subdomains { prefix, suffix } => f
+                .debug_struct("Subdomains")
+                .field("prefix", prefix)
+                .field("suffix", suffix)
+                .finish(),
+            Self::Predicate(_) => f.debug_tuple("Predicate").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl OriginPolicy {
+    /// Create a policy that denies every origin.
+    ///
+    /// Requests without an `Origin` header are still accepted, see
+    /// [`OriginPolicy::allow_missing`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow a single origin, e.g. `https://example.com` or `http://localhost:3000`.
+    pub fn allow_exact(mut self, origin: impl Into<String>) -> Self {
+        self.rules
+            .push(OriginRule::Exact(origin.into().to_ascii_lowercase()));
+        self
+    }
+
+    /// Allow all subdomains matching a wildcard pattern like `https://*.example.com`.
+    ///
+    /// The wildcard matches one or more labels, so `https://a.example.com` and
+    /// `https://a.b.example.com` are allowed but `https://example.com` is not. Add it with
+    /// [`OriginPolicy::allow_exact`] if the apex domain should be allowed as well.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` does not contain exactly one `*.` wildcard.
+    pub fn allow_subdomains(mut self, pattern: impl AsRef<str>) -> Self {
+        let pattern = pattern.as_ref().to_ascii_lowercase();
+        let (prefix, suffix) = pattern
+            .split_once("*.")
+            .filter(|(_, suffix)| !suffix.contains('*'))
+            .expect("subdomain pattern must contain exactly one `*.` wildcard");
+
+        self.rules.push(OriginRule::Subdomains {
+            prefix: prefix.to_owned(),
+            suffix: format!(".{suffix}"),
+        });
+        self
+    }
+
+    /// Allow every origin for which `predicate` returns `true`.
+    ///
+    /// The origin is passed to the predicate as sent by the client.
+    pub fn allow_fn<P>(mut self, predicate: P) -> Self
+    where
+        P: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.rules.push(OriginRule::Predicate(Arc::new(predicate)));
+        self
+    }
+
+    /// Whether requests without an `Origin` header are allowed. Defaults to `true`.
+    ///
+    /// Browsers always send the header with WebSocket handshakes, so a missing header usually
+    /// means a non-browser client that cannot be abused for cross-site requests.
+    pub fn allow_missing(mut self, allow: bool) -> Self {
+        self.allow_missing = allow;
+        self
+    }
+
+    /// Check whether `origin` is allowed by this policy.
+    pub fn is_allowed(&self, origin: Option<&str>) -> bool {
+        let Some(origin) = origin else {
+            return self.allow_missing;
+        };
+
+        let lowercase = origin.to_ascii_lowercase();
+        self.rules.iter().any(|rule| match rule {
+            OriginRule::Exact(allowed) => *allowed == lowercase,
+            OriginRule::Subdomains { prefix, suffix } => lowercase
+                .strip_prefix(prefix.as_str())
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|labels| {
+                    !labels.is_empty()
+                        && labels
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+                }),
+            OriginRule::Predicate(predicate) => predicate(origin),
+        })
+    }
+
+    pub(crate) fn check(&self, origin: Option<&HeaderValue>) -> Result<(), ForbiddenOrigin> {
+        let origin = match origin.map(HeaderValue::to_str) {
+            Some(Ok(origin)) => Some(origin),
+            Some(Err(_)) => return Err(ForbiddenOrigin),
+            None => None,
+        };
+
+        if self.is_allowed(origin) {
+            Ok(())
+        } else {
+            Err(ForbiddenOrigin)
+        }
+    }
+}
+
+define_rejection! {
+    #[status = FORBIDDEN]
+    #[body = "WebSocket connection from this origin is not allowed"]
+    /// Rejection used if the `Origin` of a request is not allowed by the [`OriginPolicy`].
+    pub struct ForbiddenOrigin;
+}
@@ -0,0 +1,344 @@
+use crate::ForbiddenOrigin;
+use crate::strict::{
+    DuplicateSecurityHeader, InvalidHostHeader, MissingConnectionUpgradeToken,
+    MissingUpgradeWebSocketToken,
+};
+use axum::body::Body;
+use axum::extract::ws::rejection::{
+    ConnectionNotUpgradable, InvalidConnectionHeader, InvalidProtocolPseudoheader,
+    InvalidUpgradeHeader, InvalidWebSocketVersionHeader, MethodNotConnect, MethodNotGet,
+    WebSocketKeyHeaderMissing, WebSocketUpgradeRejection,
+};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+/// Rejection type for [`RawSocketUpgrade`](crate::RawSocketUpgrade).
+///
+/// Contains one variant for each way the handshake can fail. Unlike axum's
+/// [`WebSocketUpgradeRejection`] it answers an unsupported `Sec-WebSocket-Version` with
+/// `426 Upgrade Required` as asked for by
+/// [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455#section-4.2.2), and in
+/// [strict mode](crate::RawSocketUpgradeLayer::strict) tells malformed `Sec-WebSocket-Key`
+/// headers apart from missing ones.
+///
+/// The rejection is stored in the extensions of the response it turns into, so
+/// [`RawSocketUpgradeLayer::render_rejection`](crate::RawSocketUpgradeLayer::render_rejection)
+/// can replace the plain text body, e.g. with
+/// [`problem_json`](RawSocketUpgradeRejection::problem_json).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RawSocketUpgradeRejection {
+    #[allow(missing_docs)]
+    MethodNotGet(MethodNotGet),
+    #[allow(missing_docs)]
+    MethodNotConnect(MethodNotConnect),
+    #[allow(missing_docs)]
+    InvalidConnectionHeader(InvalidConnectionHeader),
+    #[allow(missing_docs)]
+    InvalidUpgradeHeader(InvalidUpgradeHeader),
+    #[allow(missing_docs)]
+    InvalidProtocolPseudoheader(InvalidProtocolPseudoheader),
+    #[allow(missing_docs)]
+    UnsupportedWebSocketVersion(UnsupportedWebSocketVersion),
+    #[allow(missing_docs)]
+    WebSocketKeyHeaderMissing(WebSocketKeyHeaderMissing),
+    #[allow(missing_docs)]
+    InvalidWebSocketKey(InvalidWebSocketKey),
+    #[allow(missing_docs)]
+    MissingConnectionUpgradeToken(MissingConnectionUpgradeToken),
+    #[allow(missing_docs)]
+    MissingUpgradeWebSocketToken(MissingUpgradeWebSocketToken),
+    #[allow(missing_docs)]
+    DuplicateSecurityHeader(DuplicateSecurityHeader),
+    #[allow(missing_docs)]
+    InvalidHostHeader(InvalidHostHeader),
+    #[allow(missing_docs)]
+    ForbiddenOrigin(ForbiddenOrigin),
+    #[allow(missing_docs)]
+    ConnectionNotUpgradable(ConnectionNotUpgradable),
+}
+
+macro_rules! for_each_variant {
+    ($self:expr, $inner:ident => $body:expr) => {
+        match $self {
+            Self::MethodNotGet($inner) => $body,
+            Self::MethodNotConnect($inner) => $body,
+            Self::InvalidConnectionHeader($inner) => $body,
+            Self::InvalidUpgradeHeader($inner) => $body,
+            Self::InvalidProtocolPseudoheader($inner) => $body,
+            Self::UnsupportedWebSocketVersion($inner) => $body,
+            Self::WebSocketKeyHeaderMissing($inner) => $body,
+            Self::InvalidWebSocketKey($inner) => $body,
+            Self::MissingConnectionUpgradeToken($inner) => $body,
+            Self::MissingUpgradeWebSocketToken($inner) => $body,
+            Self::DuplicateSecurityHeader($inner) => $body,
+            Self::InvalidHostHeader($inner) => $body,
+            Self::ForbiddenOrigin($inner) => $body,
+            Self::ConnectionNotUpgradable($inner) => $body,
+        }
+    };
+}
+
+impl RawSocketUpgradeRejection {
+    /// Get the response body text used for this rejection.
+    pub fn body_text(&self) -> String {
+        for_each_variant!(self, inner => inner.body_text())
+    }
+
+    /// Get the status code used for this rejection.
+    pub fn status(&self) -> StatusCode {
+        for_each_variant!(self, inner => inner.status())
+    }
+
+    /// Render the rejection as an
+    /// [RFC 9457](https://datatracker.ietf.org/doc/html/rfc9457) `application/problem+json`
+    /// response with the same status code and headers.
+    pub fn problem_json(&self) -> Response {
+        let status = self.status();
+        let body = format!(
+            r#"{{"type":"about:blank","title":"{}","status":{},"detail":"{}"}}"#,
+            json_escape(status.canonical_reason().unwrap_or_default()),
+            status.as_u16(),
+            json_escape(&self.body_text()),
+        );
+
+        let (mut parts, _) = self.clone().into_response().into_parts();
+        parts.headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        Response::from_parts(parts, Body::from(body))
+    }
+}
+
+impl Clone for RawSocketUpgradeRejection {
+    fn clone(&self) -> Self {
+        // axum doesn't derive `Clone` for its rejections, but they are all stateless.
+        match self {
+            Self::MethodNotGet(_) => Self::MethodNotGet(Default::default()),
+            Self::MethodNotConnect(_) => Self::MethodNotConnect(Default::default()),
+            Self::InvalidConnectionHeader(_) => Self::InvalidConnectionHeader(Default::default()),
+            Self::InvalidUpgradeHeader(_) => Self::InvalidUpgradeHeader(Default::default()),
+            Self::InvalidProtocolPseudoheader(_) => {
+                Self::InvalidProtocolPseudoheader(Default::default())
+            }
+            Self::UnsupportedWebSocketVersion(_) => {
+                Self::UnsupportedWebSocketVersion(Default::default())
+            }
+            Self::WebSocketKeyHeaderMissing(_) => {
+                Self::WebSocketKeyHeaderMissing(Default::default())
+            }
+            Self::InvalidWebSocketKey(_) => Self::InvalidWebSocketKey(Default::default()),
+            Self::MissingConnectionUpgradeToken(_) => {
+                Self::MissingConnectionUpgradeToken(Default::default())
+            }
+            Self::MissingUpgradeWebSocketToken(_) => {
+                Self::MissingUpgradeWebSocketToken(Default::default())
+            }
+            Self::DuplicateSecurityHeader(inner) => Self::DuplicateSecurityHeader(inner.clone()),
+            Self::InvalidHostHeader(_) => Self::InvalidHostHeader(Default::default()),
+            Self::ForbiddenOrigin(_) => Self::ForbiddenOrigin(Default::default()),
+            Self::ConnectionNotUpgradable(_) => Self::ConnectionNotUpgradable(Default::default()),
+        }
+    }
+}
+
+impl IntoResponse for RawSocketUpgradeRejection {
+    fn into_response(self) -> Response {
+        let mut response = for_each_variant!(self.clone(), inner => inner.into_response());
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+impl std::fmt::Display for RawSocketUpgradeRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for_each_variant!(self, inner => write!(f, "{inner}"))
+    }
+}
+
+impl std::error::Error for RawSocketUpgradeRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        for_each_variant!(self, inner => Some(inner))
+    }
+}
+
+macro_rules! impl_from {
+    ($($variant:ident),+ $(,)?) => {
+        $(
+            impl From<$variant> for RawSocketUpgradeRejection {
+                fn from(inner: $variant) -> Self {
+                    Self::$variant(inner)
+                }
+            }
+        )+
+    };
+}
+
+impl_from! {
+    MethodNotGet,
+    MethodNotConnect,
+    InvalidConnectionHeader,
+    InvalidUpgradeHeader,
+    InvalidProtocolPseudoheader,
+    UnsupportedWebSocketVersion,
+    WebSocketKeyHeaderMissing,
+    InvalidWebSocketKey,
+    MissingConnectionUpgradeToken,
+    MissingUpgradeWebSocketToken,
+    DuplicateSecurityHeader,
+    InvalidHostHeader,
+    ForbiddenOrigin,
+    ConnectionNotUpgradable,
+}
+
+impl From<WebSocketUpgradeRejection> for RawSocketUpgradeRejection {
+    fn from(rejection: WebSocketUpgradeRejection) -> Self {
+        match rejection {
+            WebSocketUpgradeRejection::MethodNotGet(inner) => Self::MethodNotGet(inner),
+            WebSocketUpgradeRejection::MethodNotConnect(inner) => Self::MethodNotConnect(inner),
+            WebSocketUpgradeRejection::InvalidConnectionHeader(inner) => {
+                Self::InvalidConnectionHeader(inner)
+            }
+            WebSocketUpgradeRejection::InvalidUpgradeHeader(inner) => {
+                Self::InvalidUpgradeHeader(inner)
+            }
+            WebSocketUpgradeRejection::InvalidProtocolPseudoheader(inner) => {
+                Self::InvalidProtocolPseudoheader(inner)
+            }
+            WebSocketUpgradeRejection::InvalidWebSocketVersionHeader(_) => {
+                Self::UnsupportedWebSocketVersion(UnsupportedWebSocketVersion)
+            }
+            WebSocketUpgradeRejection::WebSocketKeyHeaderMissing(inner) => {
+                Self::WebSocketKeyHeaderMissing(inner)
+            }
+            WebSocketUpgradeRejection::ConnectionNotUpgradable(inner) => {
+                Self::ConnectionNotUpgradable(inner)
+            }
+            // axum 0.8 has no further variants, treat new ones as a generic handshake failure.
+            _ => Self::InvalidUpgradeHeader(InvalidUpgradeHeader::default()),
+        }
+    }
+}
+
+/// Convert to axum's rejection, e.g. for code written against
+/// [`WebSocketUpgrade`](axum::extract::ws::WebSocketUpgrade).
+///
+/// axum does not tell malformed keys apart, so [`RawSocketUpgradeRejection::InvalidWebSocketKey`]
+/// becomes [`WebSocketUpgradeRejection::WebSocketKeyHeaderMissing`]. The rejections of strict
+/// mode map to the variant for the affected header, or to
+/// [`WebSocketUpgradeRejection::InvalidUpgradeHeader`] for an invalid `Host`. axum has no
+/// rejection for a forbidden `Origin`, so it also becomes
+/// [`WebSocketUpgradeRejection::InvalidUpgradeHeader`].
+impl From<RawSocketUpgradeRejection> for WebSocketUpgradeRejection {
+    fn from(rejection: RawSocketUpgradeRejection) -> Self {
+        match rejection {
+            RawSocketUpgradeRejection::MethodNotGet(inner) => inner.into(),
+            RawSocketUpgradeRejection::MethodNotConnect(inner) => inner.into(),
+            RawSocketUpgradeRejection::InvalidConnectionHeader(inner) => inner.into(),
+            RawSocketUpgradeRejection::InvalidUpgradeHeader(inner) => inner.into(),
+            RawSocketUpgradeRejection::InvalidProtocolPseudoheader(inner) => inner.into(),
+            RawSocketUpgradeRejection::UnsupportedWebSocketVersion(_) => {
+                InvalidWebSocketVersionHeader::default().into()
+            }
+            RawSocketUpgradeRejection::WebSocketKeyHeaderMissing(inner) => inner.into(),
+            RawSocketUpgradeRejection::InvalidWebSocketKey(_) => {
+                WebSocketKeyHeaderMissing::default().into()
+            }
+            RawSocketUpgradeRejection::MissingConnectionUpgradeToken(_) => {
+                InvalidConnectionHeader::default().into()
+            }
+            RawSocketUpgradeRejection::MissingUpgradeWebSocketToken(_) => {
+                InvalidUpgradeHeader::default().into()
+            }
+            RawSocketUpgradeRejection::DuplicateSecurityHeader(inner) => {
+                if inner.header_name() == header::SEC_WEBSOCKET_VERSION {
+                    InvalidWebSocketVersionHeader::default().into()
+                } else {
+                    WebSocketKeyHeaderMissing::default().into()
+                }
+            }
+            RawSocketUpgradeRejection::InvalidHostHeader(_)
+            | RawSocketUpgradeRejection::ForbiddenOrigin(_) => {
+                InvalidUpgradeHeader::default().into()
+            }
+            RawSocketUpgradeRejection::ConnectionNotUpgradable(inner) => inner.into(),
+        }
+    }
+}
+
+/// Rejection type for [`RawSocketUpgrade`](crate::RawSocketUpgrade) used if the
+/// `Sec-WebSocket-Version` header is not `13`.
+///
+/// Responds with `426 Upgrade Required` and `Sec-WebSocket-Version: 13` as specified in
+/// [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455#section-4.4), naming the required
+/// protocol in `Upgrade: websocket` and `Connection: upgrade` as
+/// [RFC 9110](https://datatracker.ietf.org/doc/html/rfc9110#section-15.5.22) asks for.
+/// hyper removes both headers from HTTP/2 responses.
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct UnsupportedWebSocketVersion;
+
+impl UnsupportedWebSocketVersion {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const SUPPORTED: HeaderValue = HeaderValue::from_static("13");
+    #[allow(clippy::declare_interior_mutable_const)]
+    const UPGRADE: HeaderValue = HeaderValue::from_static("upgrade");
+    #[allow(clippy::declare_interior_mutable_const)]
+    const WEBSOCKET: HeaderValue = HeaderValue::from_static("websocket");
+
+    /// Get the response body text used for this rejection.
+    pub fn body_text(&self) -> String {
+        "`Sec-WebSocket-Version` header did not include '13'".into()
+    }
+
+    /// Get the status code used for this rejection.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::UPGRADE_REQUIRED
+    }
+}
+
+impl IntoResponse for UnsupportedWebSocketVersion {
+    fn into_response(self) -> Response {
+        (
+            self.status(),
+            [
+                (header::SEC_WEBSOCKET_VERSION, Self::SUPPORTED),
+                (header::UPGRADE, Self::WEBSOCKET),
+                (header::CONNECTION, Self::UPGRADE),
+            ],
+            self.body_text(),
+        )
+            .into_response()
+    }
+}
+
+impl std::fmt::Display for UnsupportedWebSocketVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.body_text())
+    }
+}
+
+impl std::error::Error for UnsupportedWebSocketVersion {}
+
+define_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "`Sec-WebSocket-Key` header is not a base64 encoded 16 byte nonce"]
+    /// Rejection type for [`RawSocketUpgrade`](crate::RawSocketUpgrade) used in
+    /// [strict mode](crate::RawSocketUpgradeLayer::strict) if the `Sec-WebSocket-Key` header is
+    /// present but malformed.
+    pub struct InvalidWebSocketKey;
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
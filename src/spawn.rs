@@ -0,0 +1,57 @@
+use std::future::Future;
+use std::pin::Pin;
+
+/// The task driving an upgraded connection, handed to a [`Spawner`].
+///
+/// It waits for the upgrade to complete and then runs the `on_upgrade` callback to completion.
+pub type UpgradeTask = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Runs the task driving an upgraded connection.
+///
+/// By default connections are spawned onto the ambient tokio runtime with [`TokioSpawner`]. Use
+/// [`RawSocketUpgrade::spawner`](crate::RawSocketUpgrade::spawner) to put them on a dedicated
+/// runtime or to keep track of them for graceful shutdown. Closures taking an [`UpgradeTask`]
+/// implement this trait:
+///
+/// ```
+/// use axum::response::Response;
+/// use axum_raw_websocket::{RawSocketUpgrade, UpgradeTask};
+/// use tokio_util::task::TaskTracker;
+///
+/// fn accept(upgrade: RawSocketUpgrade, tracker: TaskTracker) -> Response {
+///     upgrade
+///         .spawner(move |task: UpgradeTask| {
+///             tracker.spawn(task);
+///         })
+///         .on_upgrade(|socket| async move {
+///             // ...
+///         })
+/// }
+/// ```
+///
+/// A [`hyper::rt::Executor`] can be used the same way with
+/// `move |task| executor.execute(task)`.
+pub trait Spawner: Send + Sync + 'static {
+    /// Run `task` in the background.
+    fn spawn(&self, task: UpgradeTask);
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(UpgradeTask) + Send + Sync + 'static,
+{
+    fn spawn(&self, task: UpgradeTask) {
+        self(task)
+    }
+}
+
+/// The default [`Spawner`], running connections with [`tokio::spawn`].
+#[non_exhaustive]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioSpawner;
+
+impl Spawner for TokioSpawner {
+    fn spawn(&self, task: UpgradeTask) {
+        tokio::spawn(task);
+    }
+}
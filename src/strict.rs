@@ -0,0 +1,139 @@
+use crate::layer::UpgradePolicy;
+use crate::{InvalidWebSocketKey, RawSocketUpgradeRejection};
+use axum::http::uri::Authority;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, Version, header, request::Parts};
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+
+/// Whether [`RawSocketUpgradeLayer::strict`](crate::RawSocketUpgradeLayer::strict) applies to
+/// the request.
+pub(crate) fn is_enabled(parts: &Parts) -> bool {
+    parts
+        .extensions
+        .get::<Arc<UpgradePolicy>>()
+        .is_some_and(|policy| policy.strict)
+}
+
+/// The checks strict mode runs after the regular handshake validation.
+pub(crate) fn validate(parts: &Parts) -> Result<(), RawSocketUpgradeRejection> {
+    for name in [header::SEC_WEBSOCKET_KEY, header::SEC_WEBSOCKET_VERSION] {
+        if parts.headers.get_all(&name).iter().nth(1).is_some() {
+            return Err(DuplicateSecurityHeader { name }.into());
+        }
+    }
+
+    if parts.version <= Version::HTTP_11 {
+        if !parts
+            .headers
+            .get(header::SEC_WEBSOCKET_KEY)
+            .is_some_and(is_valid_key)
+        {
+            return Err(InvalidWebSocketKey.into());
+        }
+
+        if !is_valid_host(&parts.headers) {
+            return Err(InvalidHostHeader.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the comma separated list in the `name` headers contains `token`, compared ASCII
+/// case-insensitively.
+pub(crate) fn list_contains(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| item.trim_matches([' ', '\t']).eq_ignore_ascii_case(token))
+}
+
+/// Whether `key` is a base64 encoded 16 byte nonce as required by
+/// [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455#section-4.2.1).
+fn is_valid_key(key: &HeaderValue) -> bool {
+    use base64::engine::Engine as _;
+
+    let mut nonce = [0; 16];
+    key.len() == 24
+        && base64::engine::general_purpose::STANDARD
+            .decode_slice(key.as_bytes(), &mut nonce)
+            .is_ok_and(|len| len == 16)
+}
+
+/// Whether there is exactly one `Host` header holding a host and an optional port.
+fn is_valid_host(headers: &HeaderMap) -> bool {
+    let mut hosts = headers.get_all(header::HOST).iter();
+
+    match (hosts.next(), hosts.next()) {
+        (Some(host), None) => Authority::try_from(host.as_bytes()).is_ok_and(|authority| {
+            !authority.host().is_empty() && !authority.as_str().contains('@')
+        }),
+        _ => false,
+    }
+}
+
+/// Rejection type for [`RawSocketUpgrade`](crate::RawSocketUpgrade) used in strict mode if
+/// `Sec-WebSocket-Key` or `Sec-WebSocket-Version` appear more than once, which
+/// [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455#section-11.3.1) forbids.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DuplicateSecurityHeader {
+    name: HeaderName,
+}
+
+impl DuplicateSecurityHeader {
+    /// The header that was repeated.
+    pub fn header_name(&self) -> &HeaderName {
+        &self.name
+    }
+
+    /// Get the response body text used for this rejection.
+    pub fn body_text(&self) -> String {
+        format!("`{}` header must not appear more than once", self.name)
+    }
+
+    /// Get the status code used for this rejection.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+impl IntoResponse for DuplicateSecurityHeader {
+    fn into_response(self) -> Response {
+        (self.status(), self.body_text()).into_response()
+    }
+}
+
+impl std::fmt::Display for DuplicateSecurityHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.body_text())
+    }
+}
+
+impl std::error::Error for DuplicateSecurityHeader {}
+
+define_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "`Connection` header did not list the `upgrade` token"]
+    /// Rejection type for [`RawSocketUpgrade`](crate::RawSocketUpgrade) used in strict mode if
+    /// `upgrade` is not a token of the comma separated `Connection` list.
+    pub struct MissingConnectionUpgradeToken;
+}
+
+define_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "`Upgrade` header did not list the `websocket` token"]
+    /// Rejection type for [`RawSocketUpgrade`](crate::RawSocketUpgrade) used in strict mode if
+    /// `websocket` is not a token of the comma separated `Upgrade` list.
+    pub struct MissingUpgradeWebSocketToken;
+}
+
+define_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "`Host` header must appear exactly once and contain a host and an optional port"]
+    /// Rejection type for [`RawSocketUpgrade`](crate::RawSocketUpgrade) used in strict mode if
+    /// the `Host` header is missing, repeated or malformed.
+    pub struct InvalidHostHeader;
+}
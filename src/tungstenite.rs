@@ -0,0 +1,55 @@
+use crate::{OnFailedUpgrade, RawSocketUpgrade};
+use axum::http::HeaderValue;
+use axum::response::Response;
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::protocol::{Role, WebSocketConfig};
+
+impl<F> RawSocketUpgrade<F> {
+    /// Finalize upgrading the connection and call the provided callback with a
+    /// [`WebSocketStream`] in the server role.
+    ///
+    /// The callback also receives the subprotocol selected by [`RawSocketUpgrade::protocols`].
+    /// This allows routes that want a ready-made WebSocket to share the extractor with routes
+    /// that work on the raw socket.
+    ///
+    /// tungstenite does not implement `permessage-deflate`, so compression agreed on with
+    /// [`RawSocketUpgrade::permessage_deflate`] or the layer is dropped from the response.
+    ///
+    /// ```
+    /// use axum::response::Response;
+    /// use axum_raw_websocket::RawSocketUpgrade;
+    /// use axum_raw_websocket::tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+    ///
+    /// async fn handler(upgrade: RawSocketUpgrade) -> Response {
+    ///     let config = WebSocketConfig::default().max_message_size(Some(1 << 20));
+    ///     upgrade
+    ///         .protocols(["graphql-transport-ws"])
+    ///         .on_upgrade_tungstenite(config, |ws, protocol| async move {
+    ///             // use `ws` as a `Stream` + `Sink` of messages
+    ///         })
+    /// }
+    /// ```
+    #[must_use = "to set up the WebSocket connection, this response must be returned"]
+    pub fn on_upgrade_tungstenite<C, Fut>(
+        mut self,
+        config: WebSocketConfig,
+        callback: C,
+    ) -> Response
+    where
+        C: FnOnce(WebSocketStream<TokioIo<Upgraded>>, Option<HeaderValue>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        // tungstenite does not implement any extensions.
+        self.deflate = None;
+
+        let protocol = self.protocol.clone();
+        self.on_upgrade(move |socket| async move {
+            let ws = WebSocketStream::from_raw_socket(socket, Role::Server, Some(config)).await;
+            callback(ws, protocol).await;
+        })
+    }
+}
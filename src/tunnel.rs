@@ -0,0 +1,179 @@
+use crate::spawn::{Spawner, TokioSpawner};
+use crate::upgrade::{spawn_upgrade, take_on_upgrade};
+use crate::{DefaultOnFailedUpgrade, OnFailedUpgrade};
+use axum::body::Body;
+use axum::extract::FromRequestParts;
+use axum::extract::ws::rejection::{ConnectionNotUpgradable, MethodNotConnect};
+use axum::http::{Method, request::Parts, uri::Authority};
+use axum::response::Response;
+use hyper::upgrade::{OnUpgrade, Upgraded};
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Extractor for tunnelling a connection through an HTTP `CONNECT host:port` request, as used by
+/// forward proxies.
+///
+/// The request target must be in authority form with an explicit port and without userinfo. Once
+/// accepted, the client
+/// receives `200 OK` and the callback gets the raw connection together with the requested target,
+/// so it can open a connection to the target and copy bytes in both directions.
+///
+/// `CONNECT` requests have no path, so the handler has to be registered as the router's
+/// [`fallback`](axum::Router::fallback).
+///
+/// ```
+/// use axum::{Router, response::Response};
+/// use axum_raw_websocket::{ConnectTunnel, ForbiddenTunnelTarget};
+///
+/// async fn proxy(tunnel: ConnectTunnel) -> Result<Response, ForbiddenTunnelTarget> {
+///     Ok(tunnel
+///         .allow(|target| target.port_u16() == Some(443))?
+///         .on_upgrade(|mut client, target| async move {
+///             if let Ok(mut server) = tokio::net::TcpStream::connect(target.as_str()).await {
+///                 let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+///             }
+///         }))
+/// }
+///
+/// let app: Router = Router::new().fallback(proxy);
+/// ```
+pub struct ConnectTunnel<F = DefaultOnFailedUpgrade> {
+    authority: Authority,
+    on_upgrade: OnUpgrade,
+    on_failed_upgrade: F,
+    spawner: Arc<dyn Spawner>,
+}
+
+impl<F> std::fmt::Debug for ConnectTunnel<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectTunnel")
+            .field("authority", &self.authority)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> ConnectTunnel<F> {
+    /// The target the client wants to connect to.
+    pub fn authority(&self) -> &Authority {
+        &self.authority
+    }
+
+    /// Reject the tunnel unless `predicate` allows the target.
+    pub fn allow<P>(self, predicate: P) -> Result<Self, ForbiddenTunnelTarget>
+    where
+        P: FnOnce(&Authority) -> bool,
+    {
+        if predicate(&self.authority) {
+            Ok(self)
+        } else {
+            Err(ForbiddenTunnelTarget)
+        }
+    }
+
+    /// Provide a callback to call if upgrading the connection fails.
+    ///
+    /// See [`RawSocketUpgrade::on_failed_upgrade`](crate::RawSocketUpgrade::on_failed_upgrade).
+    pub fn on_failed_upgrade<C>(self, callback: C) -> ConnectTunnel<C>
+    where
+        C: OnFailedUpgrade,
+    {
+        ConnectTunnel {
+            authority: self.authority,
+            on_upgrade: self.on_upgrade,
+            on_failed_upgrade: callback,
+            spawner: self.spawner,
+        }
+    }
+
+    /// Run the tunnel task with `spawner` instead of [`tokio::spawn`].
+    ///
+    /// See [`RawSocketUpgrade::spawner`](crate::RawSocketUpgrade::spawner).
+    pub fn spawner<S>(mut self, spawner: S) -> Self
+    where
+        S: Spawner,
+    {
+        self.spawner = Arc::new(spawner);
+        self
+    }
+
+    /// Accept the tunnel and call the provided callback with the stream and the target.
+    #[must_use = "to set up the tunnel, this response must be returned"]
+    pub fn on_upgrade<C, Fut>(self, callback: C) -> Response
+    where
+        C: FnOnce(TokioIo<Upgraded>, Authority) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        let authority = self.authority;
+        spawn_upgrade(
+            &*self.spawner,
+            self.on_upgrade,
+            None,
+            self.on_failed_upgrade,
+            move |stream| callback(stream, authority),
+        );
+
+        Response::new(Body::empty())
+    }
+}
+
+impl<S> FromRequestParts<S> for ConnectTunnel<DefaultOnFailedUpgrade>
+where
+    S: Send + Sync,
+{
+    type Rejection = ConnectTunnelRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if parts.method != Method::CONNECT {
+            return Err(MethodNotConnect::default().into());
+        }
+
+        let authority = parts
+            .uri
+            .authority()
+            .filter(|_| parts.uri.scheme().is_none() && parts.uri.path().is_empty())
+            .filter(|authority| {
+                !authority.host().is_empty()
+                    && authority.port().is_some()
+                    && !authority.as_str().contains('@')
+            })
+            .ok_or(InvalidConnectAuthority)?
+            .clone();
+
+        let on_upgrade = take_on_upgrade(parts)?;
+
+        Ok(Self {
+            authority,
+            on_upgrade,
+            on_failed_upgrade: DefaultOnFailedUpgrade,
+            spawner: Arc::new(TokioSpawner),
+        })
+    }
+}
+
+define_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "CONNECT request target must be `host:port`"]
+    /// Rejection type for [`ConnectTunnel`] used if the request target is not in authority form,
+    /// lacks a port or contains userinfo.
+    pub struct InvalidConnectAuthority;
+}
+
+define_rejection! {
+    #[status = FORBIDDEN]
+    #[body = "Tunnelling to this target is not allowed"]
+    /// Rejection used by [`ConnectTunnel::allow`] if the target is not allowed.
+    pub struct ForbiddenTunnelTarget;
+}
+
+composite_rejection! {
+    /// Rejection used for [`ConnectTunnel`].
+    ///
+    /// Contains one variant for each way the [`ConnectTunnel`] extractor can fail.
+    pub enum ConnectTunnelRejection {
+        MethodNotConnect,
+        InvalidConnectAuthority,
+        ConnectionNotUpgradable,
+    }
+}
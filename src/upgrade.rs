@@ -0,0 +1,436 @@
+use crate::spawn::{Spawner, TokioSpawner, UpgradeTask};
+use crate::strict::list_contains;
+use crate::{DefaultOnFailedUpgrade, OnFailedUpgrade};
+use axum::Error;
+use axum::extract::FromRequestParts;
+use axum::extract::ws::rejection::ConnectionNotUpgradable;
+use axum::http::{header, request::Parts};
+use axum::response::{IntoResponse, Response};
+use hyper::upgrade::{OnUpgrade, Upgraded};
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A protocol that is negotiated through the HTTP `Upgrade` mechanism.
+///
+/// Implementations validate the request and build the response confirming the switch to the new
+/// protocol, while the [`Upgrade`] extractor takes care of handing the raw connection to the
+/// callback once the response has been sent. [`WebSocketProtocol`](crate::WebSocketProtocol) is
+/// the implementation backing [`RawSocketUpgrade`](crate::RawSocketUpgrade).
+///
+/// ```
+/// use axum::body::Body;
+/// use axum::extract::ws::rejection::ConnectionNotUpgradable;
+/// use axum::http::{StatusCode, header, request::Parts};
+/// use axum::response::{IntoResponse, Response};
+/// use axum_raw_websocket::{Upgrade, UpgradeProtocol, requests_upgrade};
+///
+/// struct MyProto;
+///
+/// enum MyProtoRejection {
+///     NotMyProto,
+///     NotUpgradable(ConnectionNotUpgradable),
+/// }
+///
+/// impl From<ConnectionNotUpgradable> for MyProtoRejection {
+///     fn from(rejection: ConnectionNotUpgradable) -> Self {
+///         Self::NotUpgradable(rejection)
+///     }
+/// }
+///
+/// impl IntoResponse for MyProtoRejection {
+///     fn into_response(self) -> Response {
+///         match self {
+///             Self::NotMyProto => StatusCode::BAD_REQUEST.into_response(),
+///             Self::NotUpgradable(rejection) => rejection.into_response(),
+///         }
+///     }
+/// }
+///
+/// impl UpgradeProtocol for MyProto {
+///     type Rejection = MyProtoRejection;
+///
+///     fn validate(parts: &Parts) -> Result<Self, Self::Rejection> {
+///         if requests_upgrade(parts, "my-proto/1") {
+///             Ok(MyProto)
+///         } else {
+///             Err(MyProtoRejection::NotMyProto)
+///         }
+///     }
+///
+///     fn response(&self) -> Response {
+///         Response::builder()
+///             .status(StatusCode::SWITCHING_PROTOCOLS)
+///             .header(header::CONNECTION, "upgrade")
+///             .header(header::UPGRADE, "my-proto/1")
+///             .body(Body::empty())
+///             .unwrap()
+///     }
+/// }
+///
+/// async fn handler(upgrade: Upgrade<MyProto>) -> Response {
+///     upgrade.on_upgrade(|socket| async move {
+///         // speak my-proto/1 on the raw socket
+///     })
+/// }
+/// ```
+pub trait UpgradeProtocol: Sized + Send + 'static {
+    /// The rejection returned if the request does not ask for this protocol.
+    ///
+    /// Requests that pass [`UpgradeProtocol::validate`] but can not be upgraded by the server are
+    /// rejected with [`ConnectionNotUpgradable`].
+    type Rejection: IntoResponse + From<ConnectionNotUpgradable>;
+
+    /// Validate the request and capture everything needed to build the response.
+    fn validate(parts: &Parts) -> Result<Self, Self::Rejection>;
+
+    /// The response confirming the upgrade, usually `101 Switching Protocols`.
+    fn response(&self) -> Response;
+}
+
+/// Check whether an HTTP/1.1 request asks to upgrade to `protocol`.
+///
+/// This is the case if `upgrade` is a token of the comma separated `Connection` list and
+/// `protocol` is a token of the comma separated `Upgrade` list, both compared ASCII
+/// case-insensitively.
+pub fn requests_upgrade(parts: &Parts, protocol: &'static str) -> bool {
+    list_contains(&parts.headers, header::CONNECTION, "upgrade")
+        && list_contains(&parts.headers, header::UPGRADE, protocol)
+}
+
+/// Extractor for establishing a connection via an arbitrary [`UpgradeProtocol`].
+///
+/// Works like [`RawSocketUpgrade`](crate::RawSocketUpgrade) but leaves validating the request and
+/// building the response to the protocol.
+pub struct Upgrade<P, F = DefaultOnFailedUpgrade> {
+    protocol: P,
+    on_upgrade: OnUpgrade,
+    on_failed_upgrade: F,
+    spawner: Arc<dyn Spawner>,
+}
+
+impl<P, F> std::fmt::Debug for Upgrade<P, F>
+where
+    P: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Upgrade")
+            .field("protocol", &self.protocol)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P, F> Upgrade<P, F> {
+    /// The validated protocol.
+    pub fn protocol(&self) -> &P {
+        &self.protocol
+    }
+
+    /// Provide a callback to call if upgrading the connection fails.
+    ///
+    /// See [`RawSocketUpgrade::on_failed_upgrade`](crate::RawSocketUpgrade::on_failed_upgrade).
+    pub fn on_failed_upgrade<C>(self, callback: C) -> Upgrade<P, C>
+    where
+        C: OnFailedUpgrade,
+    {
+        Upgrade {
+            protocol: self.protocol,
+            on_upgrade: self.on_upgrade,
+            on_failed_upgrade: callback,
+            spawner: self.spawner,
+        }
+    }
+
+    /// Run the connection task with `spawner` instead of [`tokio::spawn`].
+    ///
+    /// See [`RawSocketUpgrade::spawner`](crate::RawSocketUpgrade::spawner).
+    pub fn spawner<S>(mut self, spawner: S) -> Self
+    where
+        S: Spawner,
+    {
+        self.spawner = Arc::new(spawner);
+        self
+    }
+
+    /// Finalize upgrading the connection and call the provided callback with the stream.
+    #[must_use = "to set up the connection, this response must be returned"]
+    pub fn on_upgrade<C, Fut>(self, callback: C) -> Response
+    where
+        P: UpgradeProtocol,
+        C: FnOnce(TokioIo<Upgraded>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        spawn_upgrade(
+            &*self.spawner,
+            self.on_upgrade,
+            None,
+            self.on_failed_upgrade,
+            callback,
+        );
+        self.protocol.response()
+    }
+
+    /// Like [`Upgrade::on_upgrade`] but also hands the validated protocol to the callback.
+    #[must_use = "to set up the connection, this response must be returned"]
+    pub fn on_upgrade_with_protocol<C, Fut>(self, callback: C) -> Response
+    where
+        P: UpgradeProtocol,
+        C: FnOnce(TokioIo<Upgraded>, P) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        F: OnFailedUpgrade,
+    {
+        let response = self.protocol.response();
+        let protocol = self.protocol;
+        spawn_upgrade(
+            &*self.spawner,
+            self.on_upgrade,
+            None,
+            self.on_failed_upgrade,
+            move |socket| callback(socket, protocol),
+        );
+        response
+    }
+}
+
+impl<P, S> FromRequestParts<S> for Upgrade<P>
+where
+    P: UpgradeProtocol,
+    S: Send + Sync,
+{
+    type Rejection = P::Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let protocol = P::validate(parts)?;
+        let on_upgrade = take_on_upgrade(parts)?;
+
+        Ok(Self {
+            protocol,
+            on_upgrade,
+            on_failed_upgrade: DefaultOnFailedUpgrade,
+            spawner: Arc::new(TokioSpawner),
+        })
+    }
+}
+
+/// Take the upgrade future hyper stores in the request extensions.
+pub(crate) fn take_on_upgrade(parts: &mut Parts) -> Result<OnUpgrade, ConnectionNotUpgradable> {
+    parts
+        .extensions
+        .remove::<OnUpgrade>()
+        .ok_or_else(ConnectionNotUpgradable::default)
+}
+
+/// The error passed to [`OnFailedUpgrade`] if the client did not complete the upgrade within the
+/// timeout set with [`RawSocketUpgrade::upgrade_timeout`](crate::RawSocketUpgrade::upgrade_timeout).
+///
+/// Use [`axum::Error::into_inner`] and downcast to tell it apart from other failures:
+///
+/// ```
+/// use axum_raw_websocket::UpgradeTimeout;
+///
+/// fn on_failed_upgrade(error: axum::Error) {
+///     if error.into_inner().is::<UpgradeTimeout>() {
+///         // the client went silent after the handshake response
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpgradeTimeout {
+    timeout: Duration,
+}
+
+impl UpgradeTimeout {
+    /// The timeout that elapsed.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl std::fmt::Display for UpgradeTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "connection upgrade did not complete within {:?}",
+            self.timeout
+        )
+    }
+}
+
+impl std::error::Error for UpgradeTimeout {}
+
+/// Wait for the upgrade to complete in a task run by `spawner` and hand the connection to
+/// `callback`.
+///
+/// If `timeout` elapses first, `on_failed_upgrade` is called with an [`UpgradeTimeout`].
+pub(crate) fn spawn_upgrade<C, Fut, F>(
+    spawner: &dyn Spawner,
+    on_upgrade: OnUpgrade,
+    timeout: Option<Duration>,
+    on_failed_upgrade: F,
+    callback: C,
+) where
+    C: FnOnce(TokioIo<Upgraded>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+    F: OnFailedUpgrade,
+{
+    spawner.spawn(upgrade_task(
+        on_upgrade,
+        timeout,
+        on_failed_upgrade,
+        callback,
+    ));
+}
+
+/// The task spawned by [`spawn_upgrade`].
+pub(crate) fn upgrade_task<C, Fut, F>(
+    on_upgrade: OnUpgrade,
+    timeout: Option<Duration>,
+    on_failed_upgrade: F,
+    callback: C,
+) -> UpgradeTask
+where
+    C: FnOnce(TokioIo<Upgraded>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+    F: OnFailedUpgrade,
+{
+    Box::pin(async move {
+        let upgraded = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, on_upgrade).await {
+                Ok(upgraded) => upgraded.map_err(Error::new),
+                Err(_) => Err(Error::new(UpgradeTimeout { timeout })),
+            },
+            None => on_upgrade.await.map_err(Error::new),
+        };
+        let upgraded = match upgraded {
+            Ok(upgraded) => upgraded,
+            Err(err) => {
+                on_failed_upgrade.call_async(err).await;
+                return;
+            }
+        };
+        let upgraded: TokioIo<Upgraded> = TokioIo::new(upgraded);
+        callback(upgraded).await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use hyper::body::Incoming;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use tokio::io::{AsyncWriteExt, DuplexStream};
+    use tokio::sync::{mpsc, oneshot};
+
+    /// An `OnUpgrade` for a request the server never responds to, so it never resolves, and the
+    /// client side of the connection, which has to be kept open.
+    async fn pending_upgrade() -> (OnUpgrade, DuplexStream) {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let service = service_fn(move |mut request: Request<Incoming>| {
+            let _ = tx.send(hyper::upgrade::on(&mut request));
+            std::future::pending::<Result<Response<Body>, std::convert::Infallible>>()
+        });
+        tokio::spawn(
+            http1::Builder::new()
+                .serve_connection(TokioIo::new(server), service)
+                .with_upgrades(),
+        );
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: upgrade\r\nUpgrade: test\r\n\r\n")
+            .await
+            .unwrap();
+        (rx.recv().await.unwrap(), client)
+    }
+
+    fn parts(connection: &'static str, upgrade: &'static str) -> Parts {
+        let (parts, ()) = Request::builder()
+            .header(header::CONNECTION, connection)
+            .header(header::UPGRADE, upgrade)
+            .body(())
+            .unwrap()
+            .into_parts();
+        parts
+    }
+
+    #[test]
+    fn requests_upgrade_matches_tokens() {
+        assert!(requests_upgrade(
+            &parts("upgrade", "my-proto/1"),
+            "my-proto/1"
+        ));
+        assert!(requests_upgrade(
+            &parts("keep-alive, Upgrade", "h2c,\tMY-PROTO/1 "),
+            "my-proto/1"
+        ));
+        assert!(requests_upgrade(
+            &parts("upgrade", "my-proto/1, h2c"),
+            "my-proto/1"
+        ));
+    }
+
+    #[test]
+    fn requests_upgrade_rejects_substrings() {
+        assert!(!requests_upgrade(
+            &parts("notupgrade", "my-proto/1"),
+            "my-proto/1"
+        ));
+        assert!(!requests_upgrade(
+            &parts("upgrade-insecure", "my-proto/1"),
+            "my-proto/1"
+        ));
+        assert!(!requests_upgrade(
+            &parts("upgrade", "my-proto/10"),
+            "my-proto/1"
+        ));
+        assert!(!requests_upgrade(&parts("upgrade", "h2c"), "my-proto/1"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_reaches_on_failed_upgrade() {
+        let timeout = Duration::from_secs(30);
+        let (tx, rx) = oneshot::channel();
+        let (on_upgrade, _client) = pending_upgrade().await;
+
+        let task = upgrade_task(
+            on_upgrade,
+            Some(timeout),
+            move |error: Error| {
+                let _ = tx.send(error);
+            },
+            |_socket| async { panic!("the upgrade never completes") },
+        );
+        let started = tokio::time::Instant::now();
+        task.await;
+
+        assert_eq!(started.elapsed(), timeout);
+        let error = rx.await.unwrap().into_inner();
+        let error = error
+            .downcast_ref::<UpgradeTimeout>()
+            .expect("error is an UpgradeTimeout");
+        assert_eq!(error.timeout(), timeout);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_timeout_waits_for_the_upgrade() {
+        let (on_upgrade, _client) = pending_upgrade().await;
+        let task = upgrade_task(
+            on_upgrade,
+            None,
+            |_error: Error| panic!("the upgrade must not fail"),
+            |_socket| async {},
+        );
+
+        assert!(
+            tokio::time::timeout(Duration::from_secs(3600), task)
+                .await
+                .is_err()
+        );
+    }
+}
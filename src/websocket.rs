@@ -0,0 +1,151 @@
+use crate::strict::{self, MissingConnectionUpgradeToken, MissingUpgradeWebSocketToken};
+use crate::upgrade::UpgradeProtocol;
+use crate::{RawSocketUpgradeRejection, UnsupportedWebSocketVersion};
+use crate::{header_contains, header_eq, sign};
+use axum::body::Body;
+#[cfg(feature = "http2")]
+use axum::extract::ws::rejection::InvalidProtocolPseudoheader;
+use axum::extract::ws::rejection::{
+    InvalidConnectionHeader, InvalidUpgradeHeader, MethodNotConnect, MethodNotGet,
+    WebSocketKeyHeaderMissing,
+};
+use axum::http::{
+    Extensions, HeaderMap, HeaderValue, Method, StatusCode, Version, header, request::Parts,
+};
+use axum::response::Response;
+
+/// The WebSocket protocol as negotiated by [`RawSocketUpgrade`](crate::RawSocketUpgrade).
+///
+/// HTTP/1.1 requests are upgraded with `GET` and the `Upgrade: websocket` header as specified in
+/// [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455#section-4). HTTP/2+ requests use an
+/// extended `CONNECT` with the `:protocol` pseudo-header set to `websocket` as specified in
+/// [RFC 8441](https://datatracker.ietf.org/doc/html/rfc8441).
+///
+/// Use [`Upgrade<WebSocketProtocol>`](crate::Upgrade) to get the plain handshake without any of
+/// the negotiation offered by [`RawSocketUpgrade`](crate::RawSocketUpgrade).
+#[derive(Debug, Clone)]
+pub struct WebSocketProtocol {
+    /// `None` if HTTP/2+ WebSockets are used.
+    sec_websocket_key: Option<HeaderValue>,
+}
+
+impl UpgradeProtocol for WebSocketProtocol {
+    type Rejection = RawSocketUpgradeRejection;
+
+    fn validate(parts: &Parts) -> Result<Self, Self::Rejection> {
+        let strict = strict::is_enabled(parts);
+
+        let sec_websocket_key = if parts.version <= Version::HTTP_11 {
+            if parts.method != Method::GET {
+                return Err(MethodNotGet::default().into());
+            }
+
+            if strict {
+                if !strict::list_contains(&parts.headers, header::CONNECTION, "upgrade") {
+                    return Err(MissingConnectionUpgradeToken.into());
+                }
+            } else if !header_contains(&parts.headers, header::CONNECTION, "upgrade") {
+                return Err(InvalidConnectionHeader::default().into());
+            }
+
+            if strict {
+                if !strict::list_contains(&parts.headers, header::UPGRADE, "websocket") {
+                    return Err(MissingUpgradeWebSocketToken.into());
+                }
+            } else if !header_eq(&parts.headers, header::UPGRADE, "websocket") {
+                return Err(InvalidUpgradeHeader::default().into());
+            }
+
+            let sec_websocket_key = parts
+                .headers
+                .get(header::SEC_WEBSOCKET_KEY)
+                .ok_or(WebSocketKeyHeaderMissing::default())?;
+
+            Some(sec_websocket_key.clone())
+        } else {
+            if parts.method != Method::CONNECT {
+                return Err(MethodNotConnect::default().into());
+            }
+
+            // if this feature flag is disabled, we won’t be receiving an HTTP/2 request to begin
+            // with.
+            #[cfg(feature = "http2")]
+            if parts
+                .extensions
+                .get::<hyper::ext::Protocol>()
+                .is_none_or(|p| p.as_str() != "websocket")
+            {
+                return Err(InvalidProtocolPseudoheader::default().into());
+            }
+
+            None
+        };
+
+        if !header_eq(&parts.headers, header::SEC_WEBSOCKET_VERSION, "13") {
+            return Err(UnsupportedWebSocketVersion.into());
+        }
+
+        if strict {
+            strict::validate(parts)?;
+        }
+
+        Ok(Self { sec_websocket_key })
+    }
+
+    fn response(&self) -> Response {
+        if let Some(sec_websocket_key) = &self.sec_websocket_key {
+            // If `sec_websocket_key` was `Some`, we are using HTTP/1.1.
+
+            #[allow(clippy::declare_interior_mutable_const)]
+            const UPGRADE: HeaderValue = HeaderValue::from_static("upgrade");
+            #[allow(clippy::declare_interior_mutable_const)]
+            const WEBSOCKET: HeaderValue = HeaderValue::from_static("websocket");
+
+            Response::builder()
+                .status(StatusCode::SWITCHING_PROTOCOLS)
+                .header(header::CONNECTION, UPGRADE)
+                .header(header::UPGRADE, WEBSOCKET)
+                .header(
+                    header::SEC_WEBSOCKET_ACCEPT,
+                    sign(sec_websocket_key.as_bytes()),
+                )
+                .body(Body::empty())
+                .unwrap()
+        } else {
+            Response::new(Body::empty())
+        }
+    }
+}
+
+impl WebSocketProtocol {
+    /// The `Sec-WebSocket-Key` sent by the client, `None` for HTTP/2+ requests.
+    pub fn sec_websocket_key(&self) -> Option<&HeaderValue> {
+        self.sec_websocket_key.as_ref()
+    }
+}
+
+/// Whether a request tries to open a WebSocket, regardless of whether the handshake is valid.
+///
+/// This is the case for HTTP/1.1 requests whose `Upgrade` header lists `websocket` and for
+/// HTTP/2+ extended `CONNECT` requests with the `:protocol` pseudo-header set to `websocket`.
+/// Requests upgrading to other protocols or carrying only `Sec-WebSocket-*` headers are not
+/// attempts.
+#[cfg_attr(not(feature = "http2"), allow(unused_variables))]
+pub(crate) fn is_websocket_attempt(
+    method: &Method,
+    headers: &HeaderMap,
+    extensions: &Extensions,
+) -> bool {
+    if strict::list_contains(headers, header::UPGRADE, "websocket") {
+        return true;
+    }
+
+    #[cfg(feature = "http2")]
+    if method == Method::CONNECT {
+        return extensions
+            .get::<hyper::ext::Protocol>()
+            .is_some_and(|protocol| protocol.as_str() == "websocket");
+    }
+
+    false
+}
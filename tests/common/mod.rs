@@ -0,0 +1,76 @@
+//! Helpers shared by the integration tests that talk to a real server.
+#![allow(dead_code)]
+
+use axum::Router;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The `Sec-WebSocket-Key` header of the sample handshake from RFC 6455 section 1.2.
+pub const KEY: &str = "Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n";
+
+/// The only `Sec-WebSocket-Version` header RFC 6455 allows.
+pub const VERSION: &str = "Sec-WebSocket-Version: 13\r\n";
+
+/// The headers of a valid WebSocket handshake, apart from `Host`.
+pub const HANDSHAKE: &str = "Connection: Upgrade\r\nUpgrade: websocket\r\n\
+    Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n";
+
+/// Serve `app` on a random local port.
+pub async fn serve(app: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// Serve `app` on a random local port, with the peer address available as
+/// [`ConnectInfo`](axum::extract::ConnectInfo).
+pub async fn serve_with_connect_info(app: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, service).await.unwrap() });
+    addr
+}
+
+/// Hold back every response of `app`, so upgrades started by its handlers can never complete.
+pub fn never_respond(app: Router) -> Router {
+    app.layer(middleware::from_fn(|request, next: Next| async move {
+        let _response = next.run(request).await;
+        std::future::pending::<Response>().await
+    }))
+}
+
+/// Connect to `addr` and send `method target HTTP/1.1` with `headers` after a `Host` header.
+pub async fn send(addr: SocketAddr, method: &str, target: &str, headers: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("{method} {target} HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    stream
+}
+
+/// Read the response head, and whatever else arrived with it, from `stream`.
+pub async fn read_response(stream: &mut TcpStream) -> String {
+    let mut buf = vec![0; 4096];
+    let n = stream.read(&mut buf).await.unwrap();
+    String::from_utf8_lossy(&buf[..n]).into_owned()
+}
+
+/// Send a `GET` handshake with `headers` to `path` and return the response head, lowercased.
+pub async fn handshake(addr: SocketAddr, path: &str, headers: &str) -> String {
+    let mut stream = send(addr, "GET", path, headers).await;
+    read_response(&mut stream).await.to_ascii_lowercase()
+}
+
+/// Split `response` into its status line and everything after the head.
+pub fn split_response(response: &str) -> (String, String) {
+    let status = response.lines().next().unwrap_or_default().to_owned();
+    let rest = response
+        .split_once("\r\n\r\n")
+        .map(|(_, rest)| rest.to_owned())
+        .unwrap_or_default();
+    (status, rest)
+}
@@ -0,0 +1,91 @@
+//! Errors returned by fallible upgrade callbacks reaching the `on_connection_error` hook.
+
+use axum::Router;
+use axum::routing::any;
+use axum_raw_websocket::{Handshake, RawSocketUpgrade, UpgradeTask};
+use std::io;
+use std::net::SocketAddr;
+use tokio::sync::mpsc;
+use tokio_util::task::TaskTracker;
+
+mod common;
+
+/// Serve a fallible callback that fails if `fail` is set, with a hook reporting the errors if
+/// `hook` is set. Connection tasks run on the returned tracker.
+async fn serve(
+    fail: bool,
+    hook: bool,
+) -> (
+    SocketAddr,
+    TaskTracker,
+    mpsc::UnboundedReceiver<(String, Handshake)>,
+) {
+    let (tx, errors) = mpsc::unbounded_channel();
+    let tracker = TaskTracker::new();
+
+    let connections = tracker.clone();
+    let app = Router::new().route(
+        "/",
+        any(move |upgrade: RawSocketUpgrade| async move {
+            let mut upgrade = upgrade
+                .protocols(["chat"])
+                .spawner(move |task: UpgradeTask| {
+                    connections.spawn(task);
+                });
+            if hook {
+                upgrade = upgrade.on_connection_error(move |error, handshake| {
+                    let _ = tx.send((error.to_string(), handshake));
+                });
+            }
+
+            upgrade.on_upgrade_fallible(move |_socket| async move {
+                if fail {
+                    Err(io::Error::other("connection failed"))
+                } else {
+                    Ok(())
+                }
+            })
+        }),
+    );
+
+    (common::serve(app).await, tracker, errors)
+}
+
+/// Open a connection to `/?room=1` offering the `chat` subprotocol and wait for its task to
+/// finish.
+async fn connect(addr: SocketAddr, tracker: &TaskTracker) {
+    let headers = format!("{}Sec-WebSocket-Protocol: chat\r\n", common::HANDSHAKE);
+    let response = common::handshake(addr, "/?room=1", &headers).await;
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+
+    tracker.close();
+    tracker.wait().await;
+}
+
+#[tokio::test]
+async fn error_reaches_the_hook_with_the_handshake() {
+    let (addr, tracker, mut errors) = serve(true, true).await;
+    connect(addr, &tracker).await;
+
+    let (error, handshake) = errors.try_recv().expect("the hook was called");
+    assert_eq!(error, "connection failed");
+    assert_eq!(handshake.uri(), "/?room=1");
+    assert_eq!(handshake.headers()["sec-websocket-protocol"], "chat");
+    assert_eq!(handshake.protocol().unwrap(), "chat");
+}
+
+#[tokio::test]
+async fn hook_is_not_called_on_success() {
+    let (addr, tracker, mut errors) = serve(false, true).await;
+    connect(addr, &tracker).await;
+
+    assert!(errors.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn errors_are_ignored_without_a_hook() {
+    let (addr, tracker, mut errors) = serve(true, false).await;
+    connect(addr, &tracker).await;
+
+    assert!(errors.try_recv().is_err());
+}
@@ -0,0 +1,53 @@
+//! Async `OnFailedUpgrade` callbacks receiving the request context.
+
+use axum::Router;
+use axum::routing::any;
+use axum_raw_websocket::{FailedUpgradeContext, RawSocketUpgrade, UpgradeTask, UpgradeTimeout};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::task::TaskTracker;
+
+mod common;
+
+#[tokio::test(start_paused = true)]
+async fn async_callback_is_awaited_with_the_request_context() {
+    let (tx, mut failed) = mpsc::unbounded_channel();
+    let tracker = TaskTracker::new();
+
+    let connections = tracker.clone();
+    let app = Router::new().route(
+        "/chat",
+        any(move |upgrade: RawSocketUpgrade| async move {
+            upgrade
+                .upgrade_timeout(Duration::from_secs(10))
+                .spawner(move |task: UpgradeTask| {
+                    connections.spawn(task);
+                })
+                .on_failed_upgrade_async(async move |error, context: FailedUpgradeContext| {
+                    // Only observable before the task finishes if the task awaits the callback.
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    let _ = tx.send((error, context));
+                })
+                .on_upgrade(|_socket| async { panic!("the upgrade never completes") })
+        }),
+    );
+    let addr = common::serve(common::never_respond(app)).await;
+
+    let headers = format!("{}x-request-id: 42\r\n", common::HANDSHAKE);
+    let _client = common::send(addr, "GET", "/chat?room=1", &headers).await;
+
+    // The task is spawned by the handler, wait until it exists before closing the tracker.
+    while tracker.is_empty() {
+        tokio::task::yield_now().await;
+    }
+    tracker.close();
+    tracker.wait().await;
+
+    let (error, context) = failed
+        .try_recv()
+        .expect("the callback was awaited by the task");
+    assert!(error.into_inner().is::<UpgradeTimeout>());
+    assert_eq!(context.uri(), "/chat?room=1");
+    assert_eq!(context.headers()["x-request-id"], "42");
+    assert_eq!(context.peer_addr(), None);
+}
@@ -0,0 +1,70 @@
+//! Pre-upgrade guards rejecting requests or handing an identity to the callback.
+
+use axum::Router;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::any;
+use axum_raw_websocket::RawSocketUpgrade;
+use std::net::SocketAddr;
+use tokio::sync::mpsc;
+
+mod common;
+
+#[derive(Clone, Debug, PartialEq)]
+struct User(String);
+
+/// Serve a guarded endpoint that reports the identity reaching the callback, behind a layer
+/// that authenticates requests carrying an `x-user` header.
+async fn serve() -> (SocketAddr, mpsc::UnboundedReceiver<User>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let app = Router::new()
+        .route(
+            "/",
+            any(move |upgrade: RawSocketUpgrade| async move {
+                let upgrade = upgrade
+                    .guard(async |parts| {
+                        parts
+                            .extensions
+                            .get::<User>()
+                            .cloned()
+                            .ok_or(StatusCode::UNAUTHORIZED)
+                    })
+                    .await?;
+
+                Ok::<Response, Response>(upgrade.on_upgrade(move |_socket, user| async move {
+                    let _ = tx.send(user);
+                }))
+            }),
+        )
+        .layer(middleware::from_fn(
+            async |mut request: Request, next: Next| {
+                if let Some(name) = request.headers().get("x-user") {
+                    let user = User(name.to_str().unwrap().to_owned());
+                    request.extensions_mut().insert(user);
+                }
+                next.run(request).await
+            },
+        ));
+
+    (common::serve(app).await, rx)
+}
+
+#[tokio::test]
+async fn guard_rejects_the_upgrade() {
+    let (addr, _users) = serve().await;
+    let response = common::handshake(addr, "/", common::HANDSHAKE).await;
+    assert!(response.starts_with("http/1.1 401"), "{response}");
+}
+
+#[tokio::test]
+async fn identity_from_extensions_reaches_the_callback() {
+    let (addr, mut users) = serve().await;
+    let headers = format!("{}x-user: alice\r\n", common::HANDSHAKE);
+    let response = common::handshake(addr, "/", &headers).await;
+
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    assert_eq!(users.recv().await.unwrap(), User("alice".to_owned()));
+}
@@ -0,0 +1,57 @@
+//! The `Handshake` handed to `on_upgrade_with_handshake` callbacks.
+
+use axum::Router;
+use axum::http::{Method, Version};
+use axum::routing::any;
+use axum_raw_websocket::{DeflateConfig, Handshake, RawSocketUpgrade};
+use tokio::sync::mpsc;
+
+mod common;
+
+#[tokio::test]
+async fn callback_receives_request_and_negotiation() {
+    let (tx, mut handshakes) = mpsc::unbounded_channel::<Handshake>();
+
+    let app = Router::new().route(
+        "/chat",
+        any(move |upgrade: RawSocketUpgrade| async move {
+            upgrade
+                .protocols(["v2.chat"])
+                .permessage_deflate(DeflateConfig::new())
+                .on_upgrade_with_handshake(move |_socket, handshake| async move {
+                    let _ = tx.send(handshake);
+                })
+        }),
+    );
+    let addr = common::serve_with_connect_info(app).await;
+
+    let headers = format!(
+        "{}Sec-WebSocket-Protocol: chat, v2.chat\r\nSec-WebSocket-Extensions: permessage-deflate\r\n",
+        common::HANDSHAKE
+    );
+    let mut stream = common::send(addr, "GET", "/chat?room=1", &headers).await;
+    let response = common::read_response(&mut stream).await;
+    assert!(response.starts_with("HTTP/1.1 101"), "{response}");
+
+    let handshake = handshakes.recv().await.unwrap();
+    assert_eq!(handshake.method(), Method::GET);
+    assert_eq!(handshake.uri(), "/chat?room=1");
+    assert_eq!(handshake.query(), Some("room=1"));
+    assert_eq!(handshake.version(), Version::HTTP_11);
+    assert_eq!(handshake.headers()["host"], "localhost");
+    assert_eq!(
+        handshake.headers()["sec-websocket-protocol"],
+        "chat, v2.chat"
+    );
+    assert_eq!(handshake.protocol().unwrap(), "v2.chat");
+    assert!(handshake.deflate().is_some());
+    assert_eq!(
+        handshake
+            .extensions()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        ["permessage-deflate"]
+    );
+    assert_eq!(handshake.peer_addr(), Some(stream.local_addr().unwrap()));
+}
@@ -0,0 +1,181 @@
+//! `RawSocketUpgradeLayer` settings reaching the extracted `RawSocketUpgrade`.
+
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::Response;
+use axum::routing::any;
+use axum::{Error, Router};
+use axum_raw_websocket::{
+    DeflateConfig, RawSocketUpgrade, RawSocketUpgradeLayer, TokioSpawner, UpgradeTask,
+    UpgradeTimeout,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+mod common;
+
+const OFFERS: &str =
+    "Sec-WebSocket-Protocol: chat, v2.chat\r\nSec-WebSocket-Extensions: permessage-deflate\r\n";
+
+fn headers(name: &'static str, value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(name, HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+/// A layer setting every policy, with a spawner counting the connection tasks it runs.
+fn layer(spawned: Arc<AtomicUsize>) -> RawSocketUpgradeLayer {
+    RawSocketUpgradeLayer::new()
+        .protocols(["v2.chat"])
+        .permessage_deflate(DeflateConfig::new())
+        .upgrade_timeout(Duration::from_secs(10))
+        .with_response_headers(headers("x-policy", "layer"))
+        .spawner(move |task: UpgradeTask| {
+            spawned.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(task);
+        })
+}
+
+/// Serve `app` behind a middleware that never sends the response, so the upgrade can only time
+/// out, and return the timeout reported to `on_failed_upgrade` together with the time it took.
+async fn time_out(app: Router, mut failed: mpsc::UnboundedReceiver<Error>) -> (Duration, Duration) {
+    let app = common::never_respond(app);
+
+    let started = Instant::now();
+    let _client = common::send(common::serve(app).await, "GET", "/", common::HANDSHAKE).await;
+
+    let error = failed.recv().await.unwrap().into_inner();
+    let error = error
+        .downcast_ref::<UpgradeTimeout>()
+        .expect("error is an UpgradeTimeout");
+    (error.timeout(), started.elapsed())
+}
+
+/// Send the handshake offering subprotocols and compression to `path` and return the response
+/// head, lowercased.
+async fn handshake(addr: SocketAddr, path: &str) -> String {
+    common::handshake(addr, path, &format!("{}{OFFERS}", common::HANDSHAKE)).await
+}
+
+#[tokio::test]
+async fn layer_policy_reaches_the_upgrade() {
+    async fn handler(upgrade: RawSocketUpgrade) -> Response {
+        upgrade.on_upgrade(|_socket| async {})
+    }
+
+    let spawned = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route("/", any(handler))
+        .layer(layer(spawned.clone()));
+    let response = handshake(common::serve(app).await, "/").await;
+
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    assert!(
+        response.contains("sec-websocket-protocol: v2.chat\r\n"),
+        "{response}"
+    );
+    assert!(
+        response.contains("sec-websocket-extensions: permessage-deflate\r\n"),
+        "{response}"
+    );
+    assert!(response.contains("x-policy: layer\r\n"), "{response}");
+    assert_eq!(spawned.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn handler_overrides_layer_policy() {
+    async fn handler(upgrade: RawSocketUpgrade) -> Response {
+        let upgrade = upgrade
+            .protocols(["chat"])
+            .permessage_deflate(DeflateConfig::new().server_no_context_takeover(true))
+            .spawner(TokioSpawner::default())
+            .with_response_headers(headers("x-policy", "handler"));
+        upgrade.on_upgrade(|_socket| async {})
+    }
+
+    let spawned = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route("/", any(handler))
+        .layer(layer(spawned.clone()));
+    let response = handshake(common::serve(app).await, "/").await;
+
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    assert!(
+        response.contains("sec-websocket-protocol: chat\r\n"),
+        "{response}"
+    );
+    assert!(
+        response.contains(
+            "sec-websocket-extensions: permessage-deflate; server_no_context_takeover\r\n"
+        ),
+        "{response}"
+    );
+    // Response headers accumulate.
+    assert!(response.contains("x-policy: layer\r\n"), "{response}");
+    assert!(response.contains("x-policy: handler\r\n"), "{response}");
+    assert_eq!(spawned.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn routes_outside_the_layer_are_unaffected() {
+    async fn handler(upgrade: RawSocketUpgrade) -> Response {
+        upgrade.on_upgrade(|_socket| async {})
+    }
+
+    let spawned = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route("/", any(handler))
+        .route_layer(layer(spawned.clone()))
+        .route("/plain", any(handler));
+    let response = handshake(common::serve(app).await, "/plain").await;
+
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    assert!(!response.contains("sec-websocket-protocol"), "{response}");
+    assert!(!response.contains("sec-websocket-extensions"), "{response}");
+    assert!(!response.contains("x-policy"), "{response}");
+    assert_eq!(spawned.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn layer_timeout_reaches_on_failed_upgrade() {
+    let (tx, failed) = mpsc::unbounded_channel();
+    let app = Router::new()
+        .route(
+            "/",
+            any(move |upgrade: RawSocketUpgrade| async move {
+                upgrade
+                    .on_failed_upgrade(move |error| {
+                        let _ = tx.send(error);
+                    })
+                    .on_upgrade(|_socket| async { panic!("the upgrade never completes") })
+            }),
+        )
+        .layer(layer(Arc::default()));
+
+    let timeout = Duration::from_secs(10);
+    assert_eq!(time_out(app, failed).await, (timeout, timeout));
+}
+
+#[tokio::test(start_paused = true)]
+async fn handler_timeout_overrides_layer_timeout() {
+    let (tx, failed) = mpsc::unbounded_channel();
+    let app = Router::new()
+        .route(
+            "/",
+            any(move |upgrade: RawSocketUpgrade| async move {
+                upgrade
+                    .upgrade_timeout(Duration::from_secs(1))
+                    .on_failed_upgrade(move |error| {
+                        let _ = tx.send(error);
+                    })
+                    .on_upgrade(|_socket| async { panic!("the upgrade never completes") })
+            }),
+        )
+        .layer(layer(Arc::default()));
+
+    let timeout = Duration::from_secs(1);
+    assert_eq!(time_out(app, failed).await, (timeout, timeout));
+}
@@ -0,0 +1,116 @@
+//! Which requests `Option<RawSocketUpgrade>` treats as plain HTTP and which as (possibly
+//! malformed) WebSocket upgrades.
+
+use axum::Router;
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum_raw_websocket::RawSocketUpgrade;
+use common::{KEY, VERSION};
+
+mod common;
+
+async fn handler(upgrade: Option<RawSocketUpgrade>) -> Response {
+    match upgrade {
+        Some(upgrade) => upgrade.on_upgrade(|_socket| async {}),
+        None => "plain http".into_response(),
+    }
+}
+
+/// Send `method / HTTP/1.1` with `headers` and return the status line and the body.
+async fn request(method: &str, headers: &str) -> (String, String) {
+    let addr = common::serve(Router::new().route("/", any(handler))).await;
+    let mut stream = common::send(addr, method, "/", headers).await;
+    common::split_response(&common::read_response(&mut stream).await)
+}
+
+#[tokio::test]
+async fn plain_request_is_none() {
+    let (status, body) = request("GET", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body, "plain http");
+}
+
+#[tokio::test]
+async fn plain_post_is_none() {
+    let (status, body) = request("POST", "Content-Length: 0\r\n").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body, "plain http");
+}
+
+#[tokio::test]
+async fn other_upgrade_protocol_is_none() {
+    let (status, body) = request("GET", "Connection: Upgrade\r\nUpgrade: h2c\r\n").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body, "plain http");
+}
+
+#[tokio::test]
+async fn upgrade_merely_containing_websocket_is_none() {
+    for upgrade in ["notwebsocket", "x-websocket-foo"] {
+        let headers = format!("Connection: Upgrade\r\nUpgrade: {upgrade}\r\n{KEY}{VERSION}");
+        let (status, body) = request("GET", &headers).await;
+        assert_eq!(status, "HTTP/1.1 200 OK", "{upgrade}");
+        assert_eq!(body, "plain http", "{upgrade}");
+    }
+}
+
+#[tokio::test]
+async fn upgrade_listing_websocket_is_an_attempt() {
+    // Outside of strict mode the handshake needs `Upgrade: websocket` alone, so this is a
+    // rejected attempt rather than plain HTTP.
+    let headers = format!("Connection: Upgrade\r\nUpgrade: h2c, websocket\r\n{KEY}{VERSION}");
+    let (status, _) = request("GET", &headers).await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+}
+
+#[tokio::test]
+async fn websocket_headers_without_upgrade_are_none() {
+    let (status, body) = request("GET", &format!("{KEY}{VERSION}")).await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body, "plain http");
+}
+
+#[tokio::test]
+async fn valid_upgrade_is_some() {
+    let headers = format!("Connection: Upgrade\r\nUpgrade: websocket\r\n{KEY}{VERSION}");
+    let (status, _) = request("GET", &headers).await;
+    assert_eq!(status, "HTTP/1.1 101 Switching Protocols");
+}
+
+#[tokio::test]
+async fn upgrade_header_is_case_insensitive() {
+    let headers = format!("Connection: Upgrade\r\nUpgrade: WebSocket\r\n{KEY}{VERSION}");
+    let (status, _) = request("GET", &headers).await;
+    assert_eq!(status, "HTTP/1.1 101 Switching Protocols");
+}
+
+#[tokio::test]
+async fn upgrade_with_wrong_method_is_rejected() {
+    let headers =
+        format!("Connection: Upgrade\r\nUpgrade: websocket\r\n{KEY}{VERSION}Content-Length: 0\r\n");
+    let (status, _) = request("POST", &headers).await;
+    assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+}
+
+#[tokio::test]
+async fn upgrade_without_connection_header_is_rejected() {
+    let headers = format!("Upgrade: websocket\r\n{KEY}{VERSION}");
+    let (status, body) = request("GET", &headers).await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    assert_ne!(body, "plain http");
+}
+
+#[tokio::test]
+async fn upgrade_without_key_is_rejected() {
+    let headers = format!("Connection: Upgrade\r\nUpgrade: websocket\r\n{VERSION}");
+    let (status, _) = request("GET", &headers).await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+}
+
+#[tokio::test]
+async fn upgrade_with_wrong_version_is_rejected() {
+    let headers =
+        format!("Connection: Upgrade\r\nUpgrade: websocket\r\n{KEY}Sec-WebSocket-Version: 8\r\n");
+    let (status, _) = request("GET", &headers).await;
+    assert_eq!(status, "HTTP/1.1 426 Upgrade Required");
+}
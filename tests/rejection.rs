@@ -0,0 +1,125 @@
+//! Status codes, headers and conversions of `RawSocketUpgradeRejection`.
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::FromRequestParts;
+use axum::extract::ws::rejection::WebSocketUpgradeRejection;
+use axum::http::{Request, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum_raw_websocket::{
+    OriginPolicy, RawSocketUpgrade, RawSocketUpgradeLayer, RawSocketUpgradeRejection,
+};
+use tower::ServiceExt;
+
+/// A valid handshake request with `name` set to `value`.
+fn handshake<B>(name: header::HeaderName, value: &'static str, body: B) -> Request<B> {
+    let mut request = Request::builder()
+        .uri("/")
+        .header(header::CONNECTION, "upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_VERSION, "13")
+        .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+        .body(body)
+        .unwrap();
+    request
+        .headers_mut()
+        .insert(name, header::HeaderValue::from_static(value));
+    request
+}
+
+async fn reject(request: Request<()>) -> RawSocketUpgradeRejection {
+    let (mut parts, ()) = request.into_parts();
+    RawSocketUpgrade::from_request_parts(&mut parts, &())
+        .await
+        .expect_err("handshake should be rejected")
+}
+
+#[tokio::test]
+async fn unsupported_version_is_426_with_supported_version() {
+    let rejection = reject(handshake(header::SEC_WEBSOCKET_VERSION, "8", ())).await;
+    assert!(matches!(
+        rejection,
+        RawSocketUpgradeRejection::UnsupportedWebSocketVersion(_)
+    ));
+
+    let response = rejection.into_response();
+    assert_eq!(response.status(), StatusCode::UPGRADE_REQUIRED);
+    assert_eq!(response.headers()[header::SEC_WEBSOCKET_VERSION], "13");
+    // RFC 9110 section 15.5.22: a 426 response MUST send an Upgrade header field.
+    assert_eq!(response.headers()[header::UPGRADE], "websocket");
+    assert_eq!(response.headers()[header::CONNECTION], "upgrade");
+}
+
+#[tokio::test]
+async fn converts_from_and_to_axum_rejection() {
+    let rejection = reject(handshake(header::SEC_WEBSOCKET_VERSION, "8", ())).await;
+
+    let axum_rejection = WebSocketUpgradeRejection::from(rejection);
+    assert!(matches!(
+        axum_rejection,
+        WebSocketUpgradeRejection::InvalidWebSocketVersionHeader(_)
+    ));
+
+    let rejection = RawSocketUpgradeRejection::from(axum_rejection);
+    assert_eq!(rejection.status(), StatusCode::UPGRADE_REQUIRED);
+}
+
+#[tokio::test]
+async fn layer_renders_problem_json() {
+    async fn handler(upgrade: RawSocketUpgrade) -> Response {
+        upgrade.on_upgrade(|_socket| async {})
+    }
+
+    let app = Router::new().route("/", any(handler)).layer(
+        RawSocketUpgradeLayer::new().render_rejection(RawSocketUpgradeRejection::problem_json),
+    );
+
+    let request = handshake(header::SEC_WEBSOCKET_VERSION, "8", Body::empty());
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::UPGRADE_REQUIRED);
+    assert_eq!(response.headers()[header::SEC_WEBSOCKET_VERSION], "13");
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(
+        body,
+        r#"{"type":"about:blank","title":"Upgrade Required","status":426,"detail":"`Sec-WebSocket-Version` header did not include '13'"}"#
+    );
+}
+
+#[tokio::test]
+async fn layer_renders_forbidden_origin() {
+    async fn handler(upgrade: RawSocketUpgrade) -> Response {
+        upgrade.on_upgrade(|_socket| async {})
+    }
+
+    let app = Router::new().route("/", any(handler)).layer(
+        RawSocketUpgradeLayer::new()
+            .origin_policy(OriginPolicy::new().allow_exact("https://example.com"))
+            .render_rejection(RawSocketUpgradeRejection::problem_json),
+    );
+
+    let request = handshake(header::ORIGIN, "https://evil.example", Body::empty());
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+    assert!(matches!(
+        response.extensions().get::<RawSocketUpgradeRejection>(),
+        Some(RawSocketUpgradeRejection::ForbiddenOrigin(_))
+    ));
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(
+        body,
+        r#"{"type":"about:blank","title":"Forbidden","status":403,"detail":"WebSocket connection from this origin is not allowed"}"#
+    );
+}
@@ -0,0 +1,99 @@
+//! Compressed messages through the soketto adapter.
+#![cfg(feature = "soketto")]
+
+use axum::Router;
+use axum::routing::any;
+use axum_raw_websocket::{DeflateConfig, RawSocketUpgrade};
+use flate2::{Compress, Compression, FlushCompress};
+use std::net::SocketAddr;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+mod common;
+
+/// Serve a soketto endpoint that reports every received text message, or the receive error.
+async fn serve() -> (SocketAddr, mpsc::UnboundedReceiver<Result<String, String>>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let app = Router::new().route(
+        "/",
+        any(move |upgrade: RawSocketUpgrade| async move {
+            upgrade
+                .permessage_deflate(DeflateConfig::new())
+                .on_upgrade_soketto(move |builder, _protocol| async move {
+                    let (_sender, mut receiver) = builder.finish();
+                    loop {
+                        let mut message = Vec::new();
+                        let received = receiver
+                            .receive_data(&mut message)
+                            .await
+                            .map(|_| String::from_utf8(message).unwrap())
+                            .map_err(|error| error.to_string());
+                        let failed = received.is_err();
+                        if tx.send(received).is_err() || failed {
+                            break;
+                        }
+                    }
+                })
+        }),
+    );
+
+    (common::serve(app).await, rx)
+}
+
+/// A compressed, masked text frame as sent by a client.
+fn compressed_frame(compress: &mut Compress, text: &str) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(text.len() + 64);
+    compress
+        .compress_vec(text.as_bytes(), &mut payload, FlushCompress::Sync)
+        .unwrap();
+    assert!(payload.ends_with(&[0, 0, 0xff, 0xff]));
+    payload.truncate(payload.len() - 4);
+    assert!(payload.len() < 126);
+
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut frame = vec![0x80 | 0x40 | 0x1, 0x80 | payload.len() as u8];
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+    frame
+}
+
+#[tokio::test]
+async fn messages_of_a_client_honouring_the_response_decompress() {
+    let (addr, mut received) = serve().await;
+    let headers = format!(
+        "{}Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n",
+        common::HANDSHAKE
+    );
+    let mut stream = common::send(addr, "GET", "/", &headers).await;
+
+    let response = common::read_response(&mut stream)
+        .await
+        .to_ascii_lowercase();
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    let extensions = response
+        .lines()
+        .find_map(|line| line.strip_prefix("sec-websocket-extensions: "))
+        .unwrap();
+    assert!(
+        extensions.contains("client_no_context_takeover"),
+        "{extensions}"
+    );
+
+    // Like a browser, keep the compression context unless the server asked for a reset.
+    let mut compress = Compress::new(Compression::default(), false);
+    for _ in 0..2 {
+        let frame = compressed_frame(&mut compress, "hello hello hello");
+        stream.write_all(&frame).await.unwrap();
+        if extensions.contains("client_no_context_takeover") {
+            compress.reset();
+        }
+    }
+
+    for _ in 0..2 {
+        assert_eq!(
+            received.recv().await.unwrap(),
+            Ok("hello hello hello".to_owned())
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Strict handshake validation, using the sample handshake from RFC 6455 section 1.2.
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{HeaderName, HeaderValue, Request, Version, header};
+use axum::response::Response;
+use axum::routing::any;
+use axum_raw_websocket::{RawSocketUpgrade, RawSocketUpgradeLayer, RawSocketUpgradeRejection};
+use tower::ServiceExt;
+
+/// Name the outcome of the extraction.
+///
+/// Requests sent with `oneshot` can not actually be upgraded, so passing validation shows up as
+/// `ConnectionNotUpgradable`.
+async fn handler(upgrade: Result<RawSocketUpgrade, RawSocketUpgradeRejection>) -> String {
+    match upgrade {
+        Ok(_) | Err(RawSocketUpgradeRejection::ConnectionNotUpgradable(_)) => "valid".to_owned(),
+        Err(RawSocketUpgradeRejection::InvalidConnectionHeader(_)) => {
+            "InvalidConnectionHeader".to_owned()
+        }
+        Err(RawSocketUpgradeRejection::InvalidUpgradeHeader(_)) => {
+            "InvalidUpgradeHeader".to_owned()
+        }
+        Err(RawSocketUpgradeRejection::MissingConnectionUpgradeToken(_)) => {
+            "MissingConnectionUpgradeToken".to_owned()
+        }
+        Err(RawSocketUpgradeRejection::MissingUpgradeWebSocketToken(_)) => {
+            "MissingUpgradeWebSocketToken".to_owned()
+        }
+        Err(RawSocketUpgradeRejection::WebSocketKeyHeaderMissing(_)) => {
+            "WebSocketKeyHeaderMissing".to_owned()
+        }
+        Err(RawSocketUpgradeRejection::InvalidWebSocketKey(_)) => "InvalidWebSocketKey".to_owned(),
+        Err(RawSocketUpgradeRejection::DuplicateSecurityHeader(rejection)) => {
+            format!("DuplicateSecurityHeader({})", rejection.header_name())
+        }
+        Err(RawSocketUpgradeRejection::InvalidHostHeader(_)) => "InvalidHostHeader".to_owned(),
+        Err(rejection) => format!("other: {rejection}"),
+    }
+}
+
+/// The client handshake from RFC 6455 section 1.2.
+fn rfc_handshake() -> Vec<(HeaderName, &'static str)> {
+    vec![
+        (header::HOST, "server.example.com"),
+        (header::UPGRADE, "websocket"),
+        (header::CONNECTION, "Upgrade"),
+        (header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="),
+        (header::ORIGIN, "http://example.com"),
+        (header::SEC_WEBSOCKET_PROTOCOL, "chat, superchat"),
+        (header::SEC_WEBSOCKET_VERSION, "13"),
+    ]
+}
+
+fn with(
+    mut headers: Vec<(HeaderName, &'static str)>,
+    name: HeaderName,
+    value: &'static str,
+) -> Vec<(HeaderName, &'static str)> {
+    headers.retain(|(existing, _)| *existing != name);
+    headers.push((name, value));
+    headers
+}
+
+fn without(
+    mut headers: Vec<(HeaderName, &'static str)>,
+    name: HeaderName,
+) -> Vec<(HeaderName, &'static str)> {
+    headers.retain(|(existing, _)| *existing != name);
+    headers
+}
+
+async fn extract(layer: RawSocketUpgradeLayer, headers: Vec<(HeaderName, &'static str)>) -> String {
+    let app = Router::new().route("/chat", any(handler)).layer(layer);
+
+    let mut request = Request::builder()
+        .uri("/chat")
+        .version(Version::HTTP_11)
+        .body(Body::empty())
+        .unwrap();
+    for (name, value) in headers {
+        request
+            .headers_mut()
+            .append(name, HeaderValue::from_static(value));
+    }
+
+    let response: Response = app.oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+async fn strict(headers: Vec<(HeaderName, &'static str)>) -> String {
+    extract(RawSocketUpgradeLayer::new().strict(), headers).await
+}
+
+#[tokio::test]
+async fn rfc_sample_handshake_is_valid() {
+    assert_eq!(strict(rfc_handshake()).await, "valid");
+}
+
+#[tokio::test]
+async fn connection_is_a_token_list() {
+    // Section 4.1: the Connection header field MUST include the token "Upgrade".
+    let headers = with(rfc_handshake(), header::CONNECTION, "keep-alive, Upgrade");
+    assert_eq!(strict(headers).await, "valid");
+
+    let headers = with(rfc_handshake(), header::CONNECTION, "keep-alive,\tupgrade ");
+    assert_eq!(strict(headers).await, "valid");
+
+    let headers = with(rfc_handshake(), header::CONNECTION, "notupgrade");
+    assert_eq!(strict(headers).await, "MissingConnectionUpgradeToken");
+
+    let headers = with(
+        rfc_handshake(),
+        header::CONNECTION,
+        "keep-alive, upgrade-insecure",
+    );
+    assert_eq!(strict(headers).await, "MissingConnectionUpgradeToken");
+}
+
+#[tokio::test]
+async fn lenient_checks_apply_without_strict_mode() {
+    let headers = with(rfc_handshake(), header::CONNECTION, "notupgrade");
+    assert_eq!(
+        extract(RawSocketUpgradeLayer::new(), headers).await,
+        "valid"
+    );
+
+    // Without strict mode, failing the lenient checks keeps axum's rejections.
+    let headers = with(rfc_handshake(), header::CONNECTION, "keep-alive");
+    assert_eq!(
+        extract(RawSocketUpgradeLayer::new(), headers).await,
+        "InvalidConnectionHeader"
+    );
+
+    let headers = with(rfc_handshake(), header::UPGRADE, "websocket, h2c");
+    assert_eq!(
+        extract(RawSocketUpgradeLayer::new(), headers).await,
+        "InvalidUpgradeHeader"
+    );
+}
+
+#[tokio::test]
+async fn connection_tokens_span_repeated_headers() {
+    let mut headers = with(rfc_handshake(), header::CONNECTION, "keep-alive");
+    headers.push((header::CONNECTION, "Upgrade"));
+    assert_eq!(strict(headers).await, "valid");
+}
+
+#[tokio::test]
+async fn upgrade_is_a_token_list() {
+    // Section 4.2.1: an Upgrade header field containing the value "websocket".
+    let headers = with(rfc_handshake(), header::UPGRADE, "websocket, h2c");
+    assert_eq!(strict(headers).await, "valid");
+
+    let headers = with(rfc_handshake(), header::UPGRADE, "h2c,\tWebSocket ");
+    assert_eq!(strict(headers).await, "valid");
+
+    let headers = with(rfc_handshake(), header::UPGRADE, "h2c");
+    assert_eq!(strict(headers).await, "MissingUpgradeWebSocketToken");
+
+    let headers = with(rfc_handshake(), header::UPGRADE, "notwebsocket");
+    assert_eq!(strict(headers).await, "MissingUpgradeWebSocketToken");
+}
+
+#[tokio::test]
+async fn key_must_be_a_16_byte_nonce() {
+    // Section 4.1: the nonce MUST be a randomly selected 16-byte value that has been
+    // base64-encoded.
+    let headers = with(
+        rfc_handshake(),
+        header::SEC_WEBSOCKET_KEY,
+        "dGhlIHNhbXBsZQ==",
+    );
+    assert_eq!(strict(headers).await, "InvalidWebSocketKey");
+
+    let headers = with(
+        rfc_handshake(),
+        header::SEC_WEBSOCKET_KEY,
+        "the sample nonce",
+    );
+    assert_eq!(strict(headers).await, "InvalidWebSocketKey");
+
+    // A missing key is told apart from a malformed one.
+    let headers = without(rfc_handshake(), header::SEC_WEBSOCKET_KEY);
+    assert_eq!(strict(headers).await, "WebSocketKeyHeaderMissing");
+}
+
+#[tokio::test]
+async fn key_is_not_checked_without_strict_mode() {
+    // Like axum's `WebSocketUpgrade`, malformed keys are still upgraded by default.
+    let headers = with(
+        rfc_handshake(),
+        header::SEC_WEBSOCKET_KEY,
+        "the sample nonce",
+    );
+    assert_eq!(
+        extract(RawSocketUpgradeLayer::new(), headers).await,
+        "valid"
+    );
+}
+
+#[tokio::test]
+async fn security_headers_must_not_repeat() {
+    // Section 11.3.1: Sec-WebSocket-Key MUST NOT appear more than once in an HTTP request.
+    let mut headers = rfc_handshake();
+    headers.push((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="));
+    assert_eq!(
+        strict(headers).await,
+        "DuplicateSecurityHeader(sec-websocket-key)"
+    );
+
+    // Section 11.3.5: Sec-WebSocket-Version MUST NOT appear more than once in an HTTP request.
+    let mut headers = rfc_handshake();
+    headers.push((header::SEC_WEBSOCKET_VERSION, "13"));
+    assert_eq!(
+        strict(headers).await,
+        "DuplicateSecurityHeader(sec-websocket-version)"
+    );
+}
+
+#[tokio::test]
+async fn repeated_subprotocol_headers_are_allowed() {
+    // Section 11.3.4: Sec-WebSocket-Protocol MAY appear multiple times in an HTTP request.
+    let mut headers = rfc_handshake();
+    headers.push((header::SEC_WEBSOCKET_PROTOCOL, "v2.chat"));
+    assert_eq!(strict(headers).await, "valid");
+}
+
+#[tokio::test]
+async fn host_is_required() {
+    // Section 4.1: the request MUST contain a Host header field whose value contains /host/
+    // plus optionally ":" followed by /port/.
+    let headers = with(rfc_handshake(), header::HOST, "server.example.com:8080");
+    assert_eq!(strict(headers).await, "valid");
+
+    let headers = without(rfc_handshake(), header::HOST);
+    assert_eq!(strict(headers).await, "InvalidHostHeader");
+
+    let headers = with(rfc_handshake(), header::HOST, "user@server.example.com");
+    assert_eq!(strict(headers).await, "InvalidHostHeader");
+
+    let headers = with(rfc_handshake(), header::HOST, "server.example.com/chat");
+    assert_eq!(strict(headers).await, "InvalidHostHeader");
+
+    let mut headers = rfc_handshake();
+    headers.push((header::HOST, "other.example.com"));
+    assert_eq!(strict(headers).await, "InvalidHostHeader");
+}
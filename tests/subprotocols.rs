@@ -0,0 +1,57 @@
+//! Subprotocol negotiation over a real connection.
+
+use axum::Router;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::Response;
+use axum::routing::any;
+use axum_raw_websocket::RawSocketUpgrade;
+
+mod common;
+
+/// Selects `v2.chat` and echoes the requested protocols in `x-requested`.
+async fn handler(upgrade: RawSocketUpgrade) -> Response {
+    let requested = upgrade.requested_protocols().collect::<Vec<_>>().join("|");
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-requested", HeaderValue::from_str(&requested).unwrap());
+
+    upgrade
+        .protocols(["v2.chat"])
+        .with_response_headers(headers)
+        .on_upgrade(|_socket| async {})
+}
+
+/// Send a handshake with the additional `headers` and return the response head, lowercased.
+async fn handshake(headers: &str) -> String {
+    let addr = common::serve(Router::new().route("/", any(handler))).await;
+    common::handshake(addr, "/", &format!("{}{headers}", common::HANDSHAKE)).await
+}
+
+#[tokio::test]
+async fn protocols_are_read_from_every_header() {
+    // RFC 6455 section 11.3.4: Sec-WebSocket-Protocol MAY appear multiple times in a request.
+    let response =
+        handshake("Sec-WebSocket-Protocol: chat\r\nSec-WebSocket-Protocol: v2.chat\r\n").await;
+
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    assert!(
+        response.contains("x-requested: chat|v2.chat\r\n"),
+        "{response}"
+    );
+    assert!(
+        response.contains("sec-websocket-protocol: v2.chat\r\n"),
+        "{response}"
+    );
+}
+
+#[tokio::test]
+async fn unsupported_protocols_are_not_selected() {
+    let response = handshake("Sec-WebSocket-Protocol: chat, superchat\r\n").await;
+
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    assert!(
+        response.contains("x-requested: chat|superchat\r\n"),
+        "{response}"
+    );
+    assert!(!response.contains("sec-websocket-protocol"), "{response}");
+}
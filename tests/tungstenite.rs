@@ -0,0 +1,31 @@
+//! The tokio-tungstenite adapter over a real connection.
+#![cfg(feature = "tungstenite")]
+
+use axum::Router;
+use axum::routing::any;
+use axum_raw_websocket::tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use axum_raw_websocket::{DeflateConfig, RawSocketUpgrade};
+
+mod common;
+
+#[tokio::test]
+async fn compression_is_not_advertised() {
+    let app = Router::new().route(
+        "/",
+        any(|upgrade: RawSocketUpgrade| async move {
+            upgrade
+                .permessage_deflate(DeflateConfig::new())
+                .on_upgrade_tungstenite(WebSocketConfig::default(), |_ws, _protocol| async {})
+        }),
+    );
+    let addr = common::serve(app).await;
+
+    let headers = format!(
+        "{}Sec-WebSocket-Extensions: permessage-deflate\r\n",
+        common::HANDSHAKE
+    );
+    let response = common::handshake(addr, "/", &headers).await;
+
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    assert!(!response.contains("sec-websocket-extensions"), "{response}");
+}
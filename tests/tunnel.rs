@@ -0,0 +1,65 @@
+//! Which `CONNECT` targets `ConnectTunnel` accepts.
+
+use axum::Router;
+use axum::response::Response;
+use axum_raw_websocket::{ConnectTunnel, ForbiddenTunnelTarget};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+mod common;
+
+/// Allows port 443 and echoes the target back through the tunnel.
+async fn proxy(tunnel: ConnectTunnel) -> Result<Response, ForbiddenTunnelTarget> {
+    Ok(tunnel
+        .allow(|target| target.port_u16() == Some(443))?
+        .on_upgrade(|mut client, target| async move {
+            let _ = client.write_all(target.as_str().as_bytes()).await;
+        }))
+}
+
+/// Send `CONNECT target HTTP/1.1` and return the status line and everything after the head.
+async fn connect(target: &str) -> (String, String) {
+    let addr = common::serve(Router::new().fallback(proxy)).await;
+    // hyper holds back error responses to `CONNECT` on keep-alive connections.
+    let mut stream = common::send(addr, "CONNECT", target, "Connection: close\r\n").await;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    common::split_response(&String::from_utf8_lossy(&response))
+}
+
+#[tokio::test]
+async fn authority_target_is_tunnelled() {
+    let (status, rest) = connect("example.com:443").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(rest, "example.com:443");
+}
+
+#[tokio::test]
+async fn target_without_port_is_rejected() {
+    let (status, _) = connect("example.com").await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+}
+
+#[tokio::test]
+async fn origin_form_target_is_rejected() {
+    let (status, _) = connect("/path").await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+}
+
+#[tokio::test]
+async fn absolute_form_target_is_rejected() {
+    let (status, _) = connect("https://example.com:443/").await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+}
+
+#[tokio::test]
+async fn target_with_userinfo_is_rejected() {
+    let (status, _) = connect("user:pw@example.com:443").await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+}
+
+#[tokio::test]
+async fn allow_predicate_rejects_target() {
+    let (status, _) = connect("example.com:8080").await;
+    assert_eq!(status, "HTTP/1.1 403 Forbidden");
+}
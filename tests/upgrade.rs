@@ -0,0 +1,102 @@
+//! Upgrading to a custom protocol with the generic `Upgrade` extractor.
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::ws::rejection::ConnectionNotUpgradable;
+use axum::http::{StatusCode, header, request::Parts};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum_raw_websocket::{Upgrade, UpgradeProtocol, requests_upgrade};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+mod common;
+
+struct MyProto;
+
+enum MyProtoRejection {
+    NotMyProto,
+    NotUpgradable(ConnectionNotUpgradable),
+}
+
+impl From<ConnectionNotUpgradable> for MyProtoRejection {
+    fn from(rejection: ConnectionNotUpgradable) -> Self {
+        Self::NotUpgradable(rejection)
+    }
+}
+
+impl IntoResponse for MyProtoRejection {
+    fn into_response(self) -> Response {
+        match self {
+            Self::NotMyProto => (StatusCode::BAD_REQUEST, "not my-proto/1").into_response(),
+            Self::NotUpgradable(rejection) => rejection.into_response(),
+        }
+    }
+}
+
+impl UpgradeProtocol for MyProto {
+    type Rejection = MyProtoRejection;
+
+    fn validate(parts: &Parts) -> Result<Self, Self::Rejection> {
+        if requests_upgrade(parts, "my-proto/1") {
+            Ok(MyProto)
+        } else {
+            Err(MyProtoRejection::NotMyProto)
+        }
+    }
+
+    fn response(&self) -> Response {
+        Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::CONNECTION, "upgrade")
+            .header(header::UPGRADE, "my-proto/1")
+            .body(Body::empty())
+            .unwrap()
+    }
+}
+
+/// Echoes everything it receives on the upgraded connection.
+async fn handler(upgrade: Upgrade<MyProto>) -> Response {
+    upgrade.on_upgrade(|mut socket| async move {
+        let mut buf = [0; 64];
+        while let Ok(n @ 1..) = socket.read(&mut buf).await {
+            if socket.write_all(&buf[..n]).await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+#[tokio::test]
+async fn custom_protocol_is_upgraded() {
+    let addr = common::serve(Router::new().route("/", any(handler))).await;
+    let mut stream = common::send(
+        addr,
+        "GET",
+        "/",
+        "Connection: keep-alive, Upgrade\r\nUpgrade: my-proto/1, h2c\r\n",
+    )
+    .await;
+
+    let response = common::read_response(&mut stream)
+        .await
+        .to_ascii_lowercase();
+    assert!(response.starts_with("http/1.1 101"), "{response}");
+    assert!(response.contains("upgrade: my-proto/1\r\n"), "{response}");
+    assert!(response.ends_with("\r\n\r\n"), "{response}");
+
+    stream.write_all(b"ping").await.unwrap();
+    let mut echo = [0; 4];
+    stream.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo, b"ping");
+}
+
+#[tokio::test]
+async fn other_protocol_is_rejected() {
+    let addr = common::serve(Router::new().route("/", any(handler))).await;
+    let mut stream =
+        common::send(addr, "GET", "/", "Connection: Upgrade\r\nUpgrade: h2c\r\n").await;
+
+    let (status, body) = common::split_response(&common::read_response(&mut stream).await);
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    assert_eq!(body, "not my-proto/1");
+}